@@ -0,0 +1,156 @@
+use crate::{config, err, ExitCode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const DEFAULT_MOUSE_THRESHOLD: u32 = 50;
+/// Reason given when the user interrupts us with Ctrl-C or SIGTERM
+pub const INTERRUPTED: &str = "Interrupted";
+
+static TRIPPED: AtomicBool = AtomicBool::new(false);
+static REDEEMING: AtomicBool = AtomicBool::new(false);
+static REASON: Mutex<Option<&'static str>> = Mutex::new(None);
+#[cfg(feature = "hotkey")]
+static LISTENING: std::sync::Once = std::sync::Once::new();
+
+/// Watchdog
+/// Decides whether a running redemption should come to an emergency stop,
+/// either because the user took the mouse back, or because they pressed the abort key.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    mouse_threshold: u32,
+    key: Option<String>,
+}
+
+impl Watchdog {
+    pub fn new(config: Option<&config::Abort>) -> Watchdog {
+        match config {
+            Some(abort) => Watchdog {
+                mouse_threshold: abort.mouse_threshold,
+                key: abort.key.clone(),
+            },
+            None => Watchdog::default(),
+        }
+    }
+
+    /// Start listening for the abort key, if one was configured.
+    #[cfg(feature = "hotkey")]
+    pub fn arm(&self) {
+        let Some(name) = self.key.clone() else {
+            return;
+        };
+
+        let key: rdev::Key = match serde_json::from_value(serde_json::Value::String(name.clone())) {
+            Ok(key) => key,
+            Err(_) => {
+                err!(
+                    "Unknown abort key '{}', only mouse movement will abort",
+                    name
+                );
+                return;
+            }
+        };
+
+        LISTENING.call_once(|| {
+            std::thread::spawn(move || {
+                let listener = rdev::listen(move |event| {
+                    // The key is only ours while redeeming, the rest of the time it belongs to the game
+                    if REDEEMING.load(Ordering::SeqCst)
+                        && event.event_type == rdev::EventType::KeyPress(key)
+                    {
+                        trip("Abort key pressed");
+                    }
+                });
+
+                if let Err(e) = listener {
+                    err!("Failed to listen for the abort key: {:?}", e);
+                }
+            });
+        });
+    }
+
+    #[cfg(not(feature = "hotkey"))]
+    pub fn arm(&self) {
+        if self.key.is_some() {
+            err!("An abort key is configured, but the hotkey feature is not enabled");
+        }
+    }
+
+    /// Compares where we left the mouse with where it is now, trips if the user moved it too far.
+    pub fn check(
+        &self,
+        expected: Option<(i32, i32)>,
+        actual: Option<(i32, i32)>,
+    ) -> Result<(), &'static str> {
+        if let (Some((x, y)), Some((actual_x, actual_y))) = (expected, actual) {
+            if x.abs_diff(actual_x) > self.mouse_threshold
+                || y.abs_diff(actual_y) > self.mouse_threshold
+            {
+                trip("Mouse moved by user");
+            }
+        }
+
+        if is_tripped() {
+            return Err(reason());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog {
+            mouse_threshold: DEFAULT_MOUSE_THRESHOLD,
+            key: None,
+        }
+    }
+}
+
+/// Request an emergency stop, the first reason given sticks.
+pub fn trip(reason: &'static str) {
+    if !TRIPPED.swap(true, Ordering::SeqCst) {
+        *REASON.lock().unwrap() = Some(reason);
+    }
+}
+
+/// Clear an emergency stop, so a redemption is not aborted by one that was meant for an earlier redemption.
+pub fn reset() {
+    *REASON.lock().unwrap() = None;
+    TRIPPED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_tripped() -> bool {
+    TRIPPED.load(Ordering::SeqCst)
+}
+
+/// Whether the user interrupted us with Ctrl-C or SIGTERM.
+pub fn is_interrupted() -> bool {
+    is_tripped() && reason() == INTERRUPTED
+}
+
+pub fn reason() -> &'static str {
+    REASON.lock().unwrap().unwrap_or("Aborted")
+}
+
+/// Marks whether we are in the middle of redeeming, which is when an interrupt should be deferred.
+pub fn set_redeeming(redeeming: bool) {
+    REDEEMING.store(redeeming, Ordering::SeqCst);
+}
+
+/// Install a handler for Ctrl-C and SIGTERM.
+/// While redeeming, the current step is allowed to finish so we can clean up after ourselves,
+/// otherwise (or when interrupted a second time) we exit straight away.
+pub fn handle_signals() -> Result<(), &'static str> {
+    ctrlc::set_handler(|| {
+        if !REDEEMING.load(Ordering::SeqCst) || is_tripped() {
+            std::process::exit(ExitCode::Interrupted.into());
+        }
+
+        err!("Interrupted, finishing the current step and cleaning up (interrupt again to force quit)");
+        trip(INTERRUPTED);
+    })
+    .map_err(|e| {
+        err!("Failed to install signal handler: {}", e);
+        "Failed to install signal handler"
+    })
+}
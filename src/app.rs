@@ -1,23 +1,27 @@
+use crate::abort::Watchdog;
+use crate::cache::{self, Cache};
 use crate::cli::Args;
-use crate::config::{ConfigFile, Instructions};
-use crate::interaction::Interactor;
-use crate::{config, err, verbose, ExitCode};
+use crate::code::Code;
+use crate::config::{
+    Clipboard, ConfigFile, Endpoint, Focus, Input, Instructions, Notifications, Retry,
+};
+use crate::focus::FocusGuard;
+use crate::interaction::{join, Interactor};
+use crate::notify::Event;
+use crate::report::Dispatcher;
+use crate::source::{collect, Source};
+use crate::summary::Summary;
+use crate::webhook::Webhooks;
+use crate::{abort, checkpoint, config, err, notify, verbose, ExitCode};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 
-pub(crate) enum RunInstructions {
-    Local(LocalInstructions),
-    Remote(RemoteInstructions),
-}
-
-#[allow(dead_code)] // Can be dead code if the feature is not enabled
-pub(crate) struct RemoteInstructions {
-    pub url: Option<String>,
-    pub max_retries: u8,
-
-    pub settings: Settings,
-}
-
-pub(crate) struct LocalInstructions {
-    pub codes: Vec<String>,
+pub(crate) struct RunInstructions {
+    pub sources: Vec<Source>,
+    /// Reports dead codes back to the remote they came from
+    pub dispatcher: Option<Dispatcher>,
+    /// Posts a summary of the run
+    pub webhooks: Option<Webhooks>,
 
     pub settings: Settings,
 }
@@ -25,118 +29,259 @@ pub(crate) struct LocalInstructions {
 pub(crate) struct Settings {
     pub slow: bool,
     pub verbose: bool,
+    pub no_interaction: bool,
 
     pub instructions: Instructions,
+    pub watchdog: Watchdog,
+    pub retry: Retry,
+    pub focus: Option<Focus>,
+    pub input: Input,
+    pub clipboard: Clipboard,
+    pub notifications: Option<Notifications>,
 }
 
 pub fn run(instructions: RunInstructions) -> Result<(), &'static str> {
-    match instructions {
-        RunInstructions::Local(local) => {
-            verbose!(local.settings, "Running local..");
-            run_local(local)
+    let settings = instructions.settings;
+    let notifier = notify::from_config(settings.notifications.as_ref());
+    let failed = |e| {
+        notify::send(notifier.as_deref(), &Event::Failed(e));
+        e
+    };
+
+    verbose!(
+        settings,
+        "Collecting codes from {} source(s)..",
+        instructions.sources.len()
+    );
+    let collected = collect(&instructions.sources).map_err(failed)?;
+
+    let now = Utc::now();
+    let (expired, mut codes): (Vec<_>, Vec<_>) = collected
+        .codes
+        .into_iter()
+        .partition(|code| code.is_expired(now));
+
+    if !expired.is_empty() {
+        println!("Skipping {} expired code(s):", expired.len());
+        for code in &expired {
+            println!("  {}", code);
         }
-        RunInstructions::Remote(remote) => {
-            verbose!(remote.settings, "Running remote..");
-            run_remote(remote)
+    }
+
+    if !collected.invalid.is_empty() {
+        println!(
+            "Skipping {} entries that are not valid codes:",
+            collected.invalid.len()
+        );
+        for invalid in &collected.invalid {
+            println!("  {} (from {})", invalid.code, invalid.origin);
         }
     }
+
+    // Redeem codes that are about to expire first, codes without a known expiry last
+    codes.sort_by_key(|code| (code.expires_at.is_none(), code.expires_at));
+
+    println!("Retrieved {} codes:", codes.len());
+    for code in &codes {
+        println!("  {}", code);
+    }
+
+    let cache = Cache::from_file(&cache::path()).unwrap_or_else(|e| {
+        err!("Failed to read cache from file: {}", e);
+        Cache::new()
+    });
+    let new_codes: Vec<Code> = codes
+        .iter()
+        .map(|code| code.code.clone())
+        .filter(|code| !cache.contains(code))
+        .collect();
+    if !new_codes.is_empty() {
+        notify::send(notifier.as_deref(), &Event::NewCodes(&new_codes));
+    }
+
+    let expiry: HashMap<Code, DateTime<Utc>> = codes
+        .iter()
+        .filter_map(|code| code.expires_at.map(|at| (code.code.clone(), at)))
+        .collect();
+
+    let summary = redeem(
+        settings,
+        codes.iter().map(|code| code.code.clone()).collect(),
+        expiry,
+        instructions.webhooks.as_ref(),
+    );
+
+    if let Some(dispatcher) = &instructions.dispatcher {
+        dispatcher.send(&expired);
+    }
+
+    let summary = summary.map_err(failed)?;
+    if abort::is_tripped() {
+        failed(abort::reason());
+    }
+    if !summary.failed.is_empty() {
+        err!("Failed to redeem codes: {}", join(&summary.failed));
+        return Err("Failed to redeem at least 1 code");
+    }
+
+    Ok(())
 }
 
-fn run_local(instructions: LocalInstructions) -> Result<(), &'static str> {
-    let mut interactor = Interactor::new(
-        instructions.settings.instructions,
-        instructions.settings.slow,
-        instructions.settings.verbose,
-    )?;
-
-    match interactor.redeem_many(instructions.codes) {
-        Ok(_) => Ok(()),
-        Err(failed_codes) => {
-            err!("Failed to redeem codes: {:?}", failed_codes);
-            Err("Failed to redeem at least 1 code")
-        }
+fn redeem(
+    settings: Settings,
+    codes: Vec<Code>,
+    expiry: HashMap<Code, DateTime<Utc>>,
+    webhooks: Option<&Webhooks>,
+) -> Result<Summary, &'static str> {
+    // Only the main run can be resumed, other commands keep track of their codes themselves
+    let mut interactor = interactor(settings)?.with_checkpoint(checkpoint::path());
+
+    let result = interactor.redeem_many(codes);
+
+    let mut summary = interactor.take_summary().with_expiry(expiry);
+    // Failing before the first code, e.g. without access to the mouse, leaves nothing in the summary
+    if let Err(failed_codes) = result {
+        summary.failed = failed_codes;
+    }
+    if !summary.attempts.is_empty() {
+        println!("{}", summary);
+    }
+
+    if let Some(webhooks) = webhooks.filter(|_| summary.has_codes()) {
+        webhooks.send(&summary);
     }
+
+    Ok(summary)
 }
 
-#[cfg(not(feature = "remote"))]
-fn run_remote(_instructions: RemoteInstructions) -> Result<(), &'static str> {
-    Err("Remote feature not enabled")
+pub(crate) fn interactor(settings: Settings) -> Result<Interactor, &'static str> {
+    Ok(
+        Interactor::new(settings.instructions, settings.slow, settings.verbose)?
+            .with_watchdog(settings.watchdog)
+            .with_retry(settings.retry)
+            .with_focus(FocusGuard::new(
+                settings.focus.as_ref(),
+                !settings.no_interaction,
+            ))
+            .with_input(settings.input)
+            .with_clipboard(settings.clipboard)
+            .with_notifier(notify::from_config(settings.notifications.as_ref())),
+    )
 }
 
 #[cfg(feature = "remote")]
 #[tokio::main(flavor = "current_thread")]
-async fn run_remote(instructions: RemoteInstructions) -> Result<(), &'static str> {
-    let client = licc::client::CodesClient::new_full(None, instructions.url, None);
-    let codes: Vec<String> = crate::remote::get_codes(client, instructions.max_retries)
-        .await
-        .map(|codes| {
-            codes
-                .into_iter()
-                .map(|code| code.code)
-                .collect::<Vec<String>>()
-        })?;
-
-    println!("Retrieved {} codes: {}", codes.len(), codes.join(", "));
-
-    let mut interactor = Interactor::new(
-        instructions.settings.instructions,
-        instructions.settings.slow,
-        instructions.settings.verbose,
-    )?;
-
-    match interactor.redeem_many(codes) {
-        Ok(_) => Ok(()),
-        Err(failed_codes) => {
-            err!("Failed to redeem codes: {:?}", failed_codes);
-            Err("Failed to redeem at least 1 code")
-        }
+pub async fn submit(
+    config: &ConfigFile,
+    submission: crate::remote::Submission,
+) -> Result<(), &'static str> {
+    let remote = config
+        .remote
+        .as_ref()
+        .ok_or("No remote configured, add a remote section with an api_key to the config file")?;
+    let code = submission.code.clone();
+
+    match crate::remote::submit(remote, submission).await? {
+        Some(id) => println!("Submitted {} (id {})", code, id),
+        None => println!("Submitted {}", code),
     }
+
+    Ok(())
 }
 
 impl Settings {
     pub fn from(matches: &Args, config: &ConfigFile) -> Settings {
+        let mut input = config.input.unwrap_or_default();
+        input.mode = matches.input_mode.unwrap_or(input.mode);
+
         Settings {
             slow: matches.slow,
             verbose: matches.verbose,
+            no_interaction: matches.no_interaction,
             instructions: config.instructions,
+            watchdog: Watchdog::new(config.abort.as_ref()),
+            retry: config.retry.unwrap_or_default(),
+            focus: config.focus.clone(),
+            input,
+            clipboard: config.clipboard.unwrap_or_default(),
+            notifications: config.notifications,
         }
     }
 }
 
 impl RunInstructions {
     pub fn create(matches: Args, config: ConfigFile) -> RunInstructions {
-        let require_local_codes = config.remote.is_none()
-            && (matches.prefer_remote || config.default_strategy != config::Strategy::Remote);
-        let is_codes_empty = matches.codes.is_empty();
-
         let settings = Settings::from(&matches, &config);
+        let dispatcher = config.remote.as_ref().and_then(Dispatcher::from_config);
+        let webhooks = Webhooks::from_config(&config);
+        let max_retries = config
+            .remote
+            .as_ref()
+            .map_or(1, |remote| remote.max_retries);
 
-        if !is_codes_empty || require_local_codes {
-            if is_codes_empty {
-                err!(
-                    "No codes provided, pass --codes or permit remote retrieval of codes in the setup"
-                );
-                std::process::exit(ExitCode::LocalRunFailed.into());
-            }
-
-            RunInstructions::Local(LocalInstructions {
-                codes: matches.codes,
-                settings,
-            })
-        } else {
-            let (url, max_retries) = match config.remote {
-                None => match matches.url {
-                    None => (None, 1),
-                    Some(url) => (Some(url), 1),
-                },
-                Some(data) => (data.url.clone(), data.max_retries),
-            };
+        let http = config
+            .remote
+            .as_ref()
+            .and_then(|remote| remote.http.clone());
+
+        let mut sources = vec![];
 
-            RunInstructions::Remote(RemoteInstructions {
-                url,
+        if matches.resume {
+            sources.push(Source::Checkpoint);
+        }
+
+        if !matches.codes.is_empty() {
+            sources.push(Source::Cli(matches.codes));
+        }
+
+        sources.extend(matches.file.into_iter().map(Source::File));
+
+        let use_default_remote = matches.url.is_empty()
+            && (matches.prefer_remote
+                || matches.offline
+                || (sources.is_empty() && config.default_strategy == config::Strategy::Remote));
+
+        sources.extend(matches.url.into_iter().map(|url| Source::Remote {
+            endpoints: vec![Endpoint {
+                url: Some(url),
                 max_retries,
-                settings,
-            })
+                ..Endpoint::default()
+            }],
+            union: false,
+            offline: matches.offline,
+            http: http.clone(),
+        }));
+
+        if use_default_remote {
+            sources.push(match config.remote {
+                Some(remote) => Source::Remote {
+                    endpoints: remote.endpoints(),
+                    union: remote.union,
+                    offline: matches.offline,
+                    http: remote.http,
+                },
+                None => Source::Remote {
+                    endpoints: vec![Endpoint {
+                        max_retries,
+                        ..Endpoint::default()
+                    }],
+                    union: false,
+                    offline: matches.offline,
+                    http: None,
+                },
+            });
+        }
+
+        if sources.is_empty() {
+            err!("No codes provided, pass --codes or --file, or permit remote retrieval of codes in the setup");
+            std::process::exit(ExitCode::LocalRunFailed.into());
+        }
+
+        RunInstructions {
+            sources,
+            dispatcher,
+            webhooks,
+            settings,
         }
     }
 }
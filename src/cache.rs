@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::code::Code;
 #[cfg(feature = "cache")]
 use std::io::{BufRead, Write};
 use std::path::PathBuf;
@@ -34,22 +35,24 @@ impl Cache {
 
         let reader = std::io::BufReader::new(file);
 
+        // Entries are stored in canonical form, older caches may hold codes as they were given
         for line in reader.lines() {
-            data.push(line?);
+            let line = line?;
+            data.push(Code::parse(&line).map_or(line, |code| code.to_string()));
         }
 
         Ok(Cache { data })
     }
 
-    pub fn push(&mut self, value: String) {
+    pub fn push(&mut self, value: Code) {
         if self.data.len() >= CACHE_LIMIT {
             self.data.remove(0);
         }
 
-        self.data.push(value);
+        self.data.push(value.to_string());
     }
 
-    pub fn contains(&self, value: &str) -> bool {
+    pub fn contains(&self, value: &Code) -> bool {
         self.data.contains(&value.to_string())
     }
 
@@ -84,9 +87,9 @@ impl Cache {
         Ok(Cache::new())
     }
 
-    pub fn push(&mut self, _value: String) {}
+    pub fn push(&mut self, _value: Code) {}
 
-    pub fn contains(&self, _value: &str) -> bool {
+    pub fn contains(&self, _value: &Code) -> bool {
         false
     }
 
@@ -0,0 +1,81 @@
+use crate::code::Code;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Checkpoint
+/// Progress of the current run, so an interrupted run can be picked up again with `--resume`.
+/// Unlike the cache, failed codes are kept around so they are retried when resuming.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    pub pending: Vec<Code>,
+    pub completed: Vec<Code>,
+    pub failed: Vec<Code>,
+}
+
+pub fn path() -> PathBuf {
+    crate::config::dir().join("checkpoint.json")
+}
+
+impl Checkpoint {
+    pub fn new(codes: Vec<Code>) -> Checkpoint {
+        Checkpoint {
+            pending: codes,
+            completed: vec![],
+            failed: vec![],
+        }
+    }
+
+    pub fn from_file(path: &PathBuf) -> Result<Checkpoint, &'static str> {
+        if !path.exists() {
+            return Err("No interrupted run to resume");
+        }
+
+        let jsn = std::fs::read_to_string(path).map_err(|_| "Failed to read checkpoint file")?;
+
+        serde_json::from_str(&jsn).map_err(|_| "Failed to parse checkpoint file")
+    }
+
+    /// Codes that still need redeeming, previously failed codes first.
+    pub fn remaining(&self) -> Vec<Code> {
+        self.failed
+            .iter()
+            .chain(self.pending.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn complete(&mut self, code: &Code) {
+        self.pending.retain(|c| c != code);
+        self.failed.retain(|c| c != code);
+        self.completed.push(code.clone());
+    }
+
+    pub fn fail(&mut self, code: &Code) {
+        self.pending.retain(|c| c != code);
+        self.failed.retain(|c| c != code);
+        self.failed.push(code.clone());
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.failed.is_empty()
+    }
+
+    pub fn write(&self, path: &PathBuf) -> Result<(), &'static str> {
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap())
+                .map_err(|_| "Failed to create config directory")?;
+        }
+
+        let jsn = serde_json::to_string(&self).map_err(|_| "Failed to serialize checkpoint")?;
+
+        std::fs::write(path, jsn).map_err(|_| "Failed to write checkpoint file")
+    }
+
+    pub fn remove(path: &PathBuf) -> Result<(), &'static str> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        std::fs::remove_file(path).map_err(|_| "Failed to remove checkpoint file")
+    }
+}
@@ -1,4 +1,9 @@
+use crate::code::Code;
+use crate::config::InputMode;
+#[cfg(feature = "remote")]
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Debug, Subcommand, Clone)]
 pub enum ConfigCommand {
@@ -13,22 +18,117 @@ pub enum ConfigCommand {
 
     /// Swap strategies from local to remote or vice versa
     ChangeStrategy,
+
+    /// Contribute a code to the codes API, requires `remote.api_key` in the config file
+    #[cfg(feature = "remote")]
+    Submit {
+        code: Code,
+
+        /// When the code expires, e.g. 2024-06-01 or 2024-06-01T18:00:00Z (defaults to a week from now)
+        #[clap(long, value_parser = parse_timestamp)]
+        expires: Option<DateTime<Utc>>,
+
+        /// Who created the code, e.g. the streamer who gave it out
+        #[clap(long)]
+        creator: Option<String>,
+
+        /// Where the code was found
+        #[clap(long)]
+        creator_url: Option<String>,
+    },
+
+    /// Collect codes to redeem later in one sitting
+    Queue {
+        #[clap(subcommand)]
+        action: QueueCommand,
+    },
+
+    /// Run icredeem unattended on a schedule, with a systemd user timer
+    Schedule {
+        #[clap(subcommand)]
+        action: ScheduleCommand,
+    },
+
+    /// Listen on localhost for codes to redeem, for browser extensions, bots and other companion tools
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Port to listen on
+        #[clap(long, default_value_t = 7878)]
+        port: u16,
+    },
+
+    /// Keep running, polling the remote for new codes and redeeming them as they appear
+    #[cfg(feature = "remote")]
+    Watch {
+        /// How long to wait between polls, e.g. 30m, 1h or 1h30m
+        #[clap(long, default_value = "30m", value_parser = crate::watch::parse_interval)]
+        interval: std::time::Duration,
+
+        /// Wait for ENTER before redeeming new codes, instead of redeeming them as soon as the game is focused
+        #[clap(long)]
+        confirm: bool,
+    },
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum QueueCommand {
+    /// Queue codes, codes that were already redeemed are skipped
+    Add {
+        #[clap(required = true)]
+        codes: Vec<Code>,
+    },
+
+    /// Show the queued codes
+    List,
+
+    /// Remove codes from the queue
+    Remove {
+        #[clap(required = true)]
+        codes: Vec<Code>,
+    },
+
+    /// Remove every code from the queue
+    Clear,
+
+    /// Redeem the queued codes, failed codes stay queued
+    Run,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum ScheduleCommand {
+    /// Install and start the timer, and print an equivalent cron line
+    Install {
+        /// Time of day to run at, e.g. 09:00
+        #[clap(long, value_parser = crate::schedule::parse_time)]
+        daily: chrono::NaiveTime,
+    },
+
+    /// Show when the timer runs next
+    Status,
+
+    /// Stop the timer and remove it
+    Remove,
 }
 
 #[derive(Debug, Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
 pub struct Args {
-    /// Retrieve codes from remote API
+    /// Retrieve codes from remote API, may be given more than once
     #[clap(short, long)]
     #[cfg(feature = "remote")]
-    pub url: Option<String>,
+    pub url: Vec<String>,
 
     /// Prefer obtaining codes remotely (override)
     #[clap(long)]
     #[cfg(feature = "remote")]
     pub prefer_remote: bool,
 
+    /// Only use the codes last retrieved from the remote API, without connecting to it
+    #[clap(long)]
+    #[cfg(feature = "remote")]
+    pub offline: bool,
+
     /// Clears the redeemed code cache
     #[clap(long)]
     #[cfg(feature = "cache")]
@@ -37,7 +137,7 @@ pub struct Args {
     /// Unused
     #[cfg(not(feature = "remote"))]
     #[clap(hide = true)]
-    pub url: Option<String>,
+    pub url: Vec<String>,
 
     /// Unused
     #[cfg(not(feature = "remote"))]
@@ -45,9 +145,23 @@ pub struct Args {
     #[clap(hide = true)]
     pub prefer_remote: bool,
 
+    /// Unused
+    #[cfg(not(feature = "remote"))]
+    #[clap(long)]
+    #[clap(hide = true)]
+    pub offline: bool,
+
     /// Provide codes manually
     #[clap(short, long)]
-    pub codes: Vec<String>,
+    pub codes: Vec<Code>,
+
+    /// Read codes from a file, one per line
+    #[clap(short, long)]
+    pub file: Vec<PathBuf>,
+
+    /// Continue an interrupted run, retrying codes that failed
+    #[clap(long)]
+    pub resume: bool,
 
     /// Prints the config directory
     #[clap(subcommand)]
@@ -57,6 +171,10 @@ pub struct Args {
     #[clap(long)]
     pub no_interaction: bool,
 
+    /// How to enter codes in-game, overrides the config file
+    #[clap(long, value_enum)]
+    pub input_mode: Option<InputMode>,
+
     /// Perform actions slower (guarantees success on slower systems), no effect if config already set to slow
     #[clap(long)]
     pub slow: bool,
@@ -66,13 +184,18 @@ pub struct Args {
     pub verbose: bool,
 }
 
+#[cfg(feature = "remote")]
+fn parse_timestamp(input: &str) -> Result<DateTime<Utc>, String> {
+    crate::remote::parse_timestamp(input).ok_or_else(|| format!("Invalid date '{}'", input))
+}
+
 pub fn parser() -> Result<Args, &'static str> {
     validate(Args::parse())
 }
 
 pub fn validate(args: Args) -> Result<Args, &'static str> {
-    if args.url.is_some() && !args.codes.is_empty() {
-        return Err("Cannot use both --url and --codes");
+    if args.file.iter().any(|path| !path.is_file()) {
+        return Err("Code file given with --file does not exist");
     }
 
     Ok(args)
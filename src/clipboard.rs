@@ -2,17 +2,32 @@ use crate::{err, verbose};
 
 pub struct ClipboardIsolation {
     verbose: bool,
+    exclude_from_history: bool,
     clipboard: arboard::Clipboard,
-    previous_clipboard: Option<String>,
+    previous_clipboard: Option<Snapshot>,
+}
+
+/// Whatever the user had on their clipboard before we isolated it
+enum Snapshot {
+    Text(String),
+    Html {
+        html: String,
+        alt_text: Option<String>,
+    },
+    #[cfg(feature = "image")]
+    Image(arboard::ImageData<'static>),
 }
 
 /// ClipboardIsolation
 /// Aims to offer a robust way to isolate the clipboard, and restore it when done.
 /// Avoids exposing the users clipboard contents to the application or stdout.
+///
+/// The clipboard is read once when isolating and restored once when dropped,
+/// so a whole batch of codes can be swapped in without bothering clipboard managers in between.
 impl ClipboardIsolation {
     pub fn isolate(
-        new_clipboard: String,
         verbose: bool,
+        exclude_from_history: bool,
     ) -> Result<ClipboardIsolation, &'static str> {
         let cb = arboard::Clipboard::new().map_err(|err| {
             err!("Failed to initialize clipboard: {}", err.to_string());
@@ -22,19 +37,35 @@ impl ClipboardIsolation {
         let mut isolation = ClipboardIsolation {
             clipboard: cb,
             previous_clipboard: None,
+            exclude_from_history,
             verbose,
         };
 
         isolation.start()?;
-        isolation.write_clipboard(&new_clipboard, true)?;
 
         Ok(isolation)
     }
 
+    /// Place a code on the clipboard, and verify it is what will be pasted.
+    pub fn swap(&mut self, contents: &str) -> Result<(), &'static str> {
+        self.write_clipboard(contents)?;
+
+        let written = self.clipboard.get_text().map_err(|err| {
+            err!("Failed to read clipboard: {}", err);
+            "Failed to read from clipboard"
+        })?;
+
+        if written != contents {
+            return Err("Clipboard contents do not match the code");
+        }
+
+        Ok(())
+    }
+
     fn start(&mut self) -> Result<(), &'static str> {
         verbose!(self, "==> Isolating clipboard");
 
-        self.previous_clipboard = Some(self.read_clipboard()?);
+        self.previous_clipboard = self.read_clipboard()?;
 
         Ok(())
     }
@@ -42,37 +73,101 @@ impl ClipboardIsolation {
     fn end(&mut self) -> Result<(), &'static str> {
         verbose!(self, "==> Resetting clipboard");
 
-        let prev = self.previous_clipboard.clone();
-        self.previous_clipboard = None;
-
-        match prev {
-            Some(text) => self.write_clipboard(&text, false),
+        match self.previous_clipboard.take() {
+            Some(snapshot) => self.restore_clipboard(snapshot),
             None => {
                 Ok(()) // No previous clipboard to restore
             }
         }
     }
 
-    fn write_clipboard(&mut self, contents: &str, show: bool) -> Result<(), &'static str> {
-        if show {
-            verbose!(self, "==> Writing '{}' to clipboard", contents);
-        } else {
-            verbose!(self, "==> Writing contents to clipboard");
+    fn write_clipboard(&mut self, contents: &str) -> Result<(), &'static str> {
+        verbose!(self, "==> Writing '{}' to clipboard", contents);
+
+        let mut set = self.clipboard.set();
+        if self.exclude_from_history {
+            set = exclude_from_history(set);
         }
 
-        self.clipboard.set_text(contents).map_err(|err| {
+        set.text(contents).map_err(|err| {
             err!("Failed to write code to clipboard: {}", err);
             "Failed to write to clipboard"
         })
     }
 
-    fn read_clipboard(&mut self) -> Result<String, &'static str> {
+    fn restore_clipboard(&mut self, snapshot: Snapshot) -> Result<(), &'static str> {
+        verbose!(self, "==> Restoring clipboard contents");
+
+        let result = match snapshot {
+            Snapshot::Text(text) => self.clipboard.set_text(text),
+            Snapshot::Html { html, alt_text } => self.clipboard.set_html(html, alt_text),
+            #[cfg(feature = "image")]
+            Snapshot::Image(image) => self.clipboard.set_image(image),
+        };
+
+        result.map_err(|err| {
+            err!("Failed to restore clipboard: {}", err);
+            "Failed to write to clipboard"
+        })
+    }
+
+    /// Reads the richest content available, images first as copying one often comes with HTML as well.
+    /// An empty clipboard, or content we cannot read, means there is nothing to restore.
+    fn read_clipboard(&mut self) -> Result<Option<Snapshot>, &'static str> {
         verbose!(self, "==> Reading from clipboard");
 
-        self.clipboard.get_text().map_err(|err| {
+        #[cfg(feature = "image")]
+        if let Some(image) = available(self.clipboard.get_image())? {
+            return Ok(Some(Snapshot::Image(image)));
+        }
+
+        if let Some(html) = available(self.clipboard.get().html())? {
+            let alt_text = available(self.clipboard.get_text())?;
+            return Ok(Some(Snapshot::Html { html, alt_text }));
+        }
+
+        Ok(available(self.clipboard.get_text())?.map(Snapshot::Text))
+    }
+}
+
+/// Mark the contents as sensitive, so clipboard managers leave them out of their history.
+/// On Linux this is the `x-kde-passwordManagerHint` MIME type, which most clipboard managers honour.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn exclude_from_history(set: arboard::Set<'_>) -> arboard::Set<'_> {
+    use arboard::SetExtLinux;
+    set.exclude_from_history()
+}
+
+#[cfg(windows)]
+fn exclude_from_history(set: arboard::Set<'_>) -> arboard::Set<'_> {
+    use arboard::SetExtWindows;
+    set.exclude_from_history()
+}
+
+#[cfg(target_os = "macos")]
+fn exclude_from_history(set: arboard::Set<'_>) -> arboard::Set<'_> {
+    use arboard::SetExtApple;
+    set.exclude_from_history()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn exclude_from_history(set: arboard::Set<'_>) -> arboard::Set<'_> {
+    set
+}
+
+fn available<T>(result: Result<T, arboard::Error>) -> Result<Option<T>, &'static str> {
+    match result {
+        Ok(contents) => Ok(Some(contents)),
+        Err(arboard::Error::ContentNotAvailable)
+        | Err(arboard::Error::ConversionFailure)
+        | Err(arboard::Error::ClipboardNotSupported) => Ok(None),
+        Err(err) => {
             err!("Failed to read clipboard: {}", err);
-            "Failed to read from clipboard"
-        })
+            Err("Failed to read from clipboard")
+        }
     }
 }
 
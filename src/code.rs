@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+const CHEST_CODE_LENGTH_SHORT: usize = 12;
+const CHEST_CODE_LENGTH_LONG: usize = 16;
+const CHEST_CODE_GROUP_SIZE: usize = 4;
+// Next to letters and digits, codes sometimes contain these
+const SPECIAL_CHARACTERS: &str = "!@#$%^&*";
+
+/// Code
+/// A chest code that has been normalized (uppercase, no dashes or whitespace) and validated.
+/// Displays in its canonical dashed form, e.g. `ABCD-EFGH-IJKL`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Code(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Short,
+    Long,
+}
+
+impl Code {
+    pub fn parse(input: &str) -> Result<Code, String> {
+        let normalized: String = input
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+
+        if let Some(c) = normalized
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !SPECIAL_CHARACTERS.contains(*c))
+        {
+            return Err(format!("Code contains invalid character '{}'", c));
+        }
+
+        if normalized.len() != CHEST_CODE_LENGTH_SHORT && normalized.len() != CHEST_CODE_LENGTH_LONG
+        {
+            return Err(format!(
+                "Code must be {} or {} characters long",
+                CHEST_CODE_LENGTH_SHORT, CHEST_CODE_LENGTH_LONG
+            ));
+        }
+
+        Ok(Code(normalized))
+    }
+
+    /// The code without dashes, as it is entered in-game.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self.0.len() {
+            CHEST_CODE_LENGTH_SHORT => Kind::Short,
+            _ => Kind::Long,
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Codes only consist of ASCII characters, so byte chunks are character chunks
+        let groups: Vec<&str> = self
+            .0
+            .as_bytes()
+            .chunks(CHEST_CODE_GROUP_SIZE)
+            .map(|group| std::str::from_utf8(group).unwrap())
+            .collect();
+
+        write!(f, "{}", groups.join("-"))
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Short => write!(f, "short"),
+            Kind::Long => write!(f, "long"),
+        }
+    }
+}
+
+impl FromStr for Code {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Code::parse(s)
+    }
+}
+
+impl TryFrom<String> for Code {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Code::parse(&value)
+    }
+}
+
+impl From<Code> for String {
+    fn from(code: Code) -> Self {
+        code.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // A raw code of either length, built from the characters codes are made of
+    fn raw_code() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[A-Z0-9!@#$%^&*]{12}".prop_map(String::from),
+            "[A-Z0-9!@#$%^&*]{16}".prop_map(String::from),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn parses_what_it_displays(raw in raw_code()) {
+            let code = Code::parse(&raw).unwrap();
+
+            prop_assert_eq!(Code::parse(&code.to_string()).unwrap(), code);
+        }
+
+        #[test]
+        fn normalization_is_idempotent(raw in raw_code()) {
+            let code = Code::parse(&raw.to_lowercase()).unwrap();
+
+            prop_assert_eq!(code.as_str(), raw.as_str());
+            prop_assert_eq!(Code::parse(code.as_str()).unwrap(), code);
+        }
+
+        #[test]
+        fn rejects_other_characters(
+            raw in raw_code(),
+            position in 0usize..12,
+            c in any::<char>().prop_filter("a code character", |c| {
+                !c.is_ascii_alphanumeric()
+                    && !SPECIAL_CHARACTERS.contains(*c)
+                    && *c != '-'
+                    && !c.is_whitespace()
+            }),
+        ) {
+            let mut chars: Vec<char> = raw.chars().collect();
+            chars[position] = c;
+            let input: String = chars.into_iter().collect();
+
+            prop_assert!(Code::parse(&input).is_err());
+        }
+
+        #[test]
+        fn rejects_other_lengths(raw in "[A-Z0-9]{0,32}") {
+            prop_assume!(raw.len() != CHEST_CODE_LENGTH_SHORT && raw.len() != CHEST_CODE_LENGTH_LONG);
+
+            prop_assert!(Code::parse(&raw).is_err());
+        }
+
+        #[test]
+        fn kind_follows_length(raw in raw_code()) {
+            let expected = match raw.len() {
+                CHEST_CODE_LENGTH_SHORT => Kind::Short,
+                _ => Kind::Long,
+            };
+
+            prop_assert_eq!(Code::parse(&raw).unwrap().kind(), expected);
+        }
+    }
+}
@@ -16,6 +16,16 @@ pub struct ConfigFile {
 
     pub instructions: Instructions,
     pub remote: Option<Remote>,
+    pub abort: Option<Abort>,
+    pub retry: Option<Retry>,
+    pub focus: Option<Focus>,
+    pub input: Option<Input>,
+    pub clipboard: Option<Clipboard>,
+    pub notifications: Option<Notifications>,
+    /// Where to post a summary after every run
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    pub serve: Option<Serve>,
 
     pub slow: bool,
 }
@@ -25,6 +35,168 @@ pub struct ConfigFile {
 pub struct Remote {
     pub url: Option<String>,
     pub max_retries: u8,
+    /// Mirrors of the codes API, tried in order. When empty, `url` and `max_retries` are used
+    #[serde(default)]
+    pub endpoints: Vec<Endpoint>,
+    /// Retrieve codes from every endpoint and combine them, instead of stopping at the first that answers
+    #[serde(default)]
+    pub union: bool,
+    /// Key for submitting codes to the API at `url`
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Report dead codes back to the endpoint that listed them (requires `api_key`)
+    #[serde(default)]
+    pub reporting: Option<Reporting>,
+    /// Proxy, certificates and timeouts used to connect to the API
+    #[serde(default)]
+    pub http: Option<Http>,
+}
+
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Http {
+    /// Send all requests through this proxy, e.g. "http://proxy.example.com:8080"
+    pub proxy: Option<String>,
+    /// PEM files with certificates to trust on top of the system ones, e.g. for a private CA
+    #[serde(default)]
+    pub root_certificates: Vec<PathBuf>,
+    /// Seconds to wait for a connection
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds to wait for a whole request, including reading the response
+    pub request_timeout_secs: Option<u64>,
+    pub user_agent: Option<String>,
+}
+
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reporting {
+    pub enabled: bool,
+    /// How many codes to report per request
+    pub batch_size: usize,
+    /// Milliseconds to wait between requests
+    pub delay_ms: u64,
+}
+
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Endpoint {
+    /// Base url of the API, the public instance if not set
+    pub url: Option<String>,
+    pub max_retries: u8,
+    /// Seconds to wait for a response before trying again
+    pub timeout_secs: Option<u64>,
+    /// Seconds to keep retrying for before giving up on this endpoint
+    #[serde(default)]
+    pub max_total_secs: Option<u64>,
+    /// What the endpoint returns, an `idle_champions_codes_api` instance by default
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum Format {
+    /// An `idle_champions_codes_api` instance
+    #[default]
+    Licc,
+    /// A JSON document. `codes` is a JSON pointer to the list of codes ("" if the document is the list),
+    /// `code` and `expires_at` point into every entry of that list (entries are the codes themselves if `code` is not set)
+    Json {
+        codes: String,
+        code: Option<String>,
+        expires_at: Option<String>,
+    },
+    /// Plain text, one code per line
+    Text,
+    /// An RSS or Atom feed, codes are picked out of every item (requires the `feeds` feature)
+    Rss,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum WebhookFormat {
+    /// A Discord channel webhook
+    Discord,
+    /// A Slack incoming webhook
+    Slack,
+    /// The codes that were redeemed, skipped and failed as JSON lists
+    #[default]
+    Json,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Abort {
+    /// How many pixels the mouse may be moved away from where we left it before aborting
+    pub mouse_threshold: u32,
+    /// Name of the key that aborts a redemption, e.g. "Escape" or "F12" (requires the `hotkey` feature)
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct Retry {
+    /// How many more times a failed code is tried straight away
+    pub attempts: u8,
+    /// Milliseconds to wait before the first retry, doubled for every following retry
+    pub delay_ms: u64,
+    /// Try codes that still failed once more at the end of the batch
+    pub final_pass: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Focus {
+    /// Verify the game window has focus before sending any input
+    pub check: bool,
+    /// (Part of) the title of the game window
+    pub window_title: String,
+    /// Try to bring the game window to the front when it is not focused
+    pub raise: bool,
+    /// Seconds to wait for the game to be focused again before giving up on the code
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone, clap::ValueEnum)]
+pub enum InputMode {
+    /// Paste the code from the clipboard
+    Paste,
+    /// Type the code one key at a time
+    Type,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct Input {
+    pub mode: InputMode,
+    /// Milliseconds to wait between keystrokes when typing a code
+    pub keystroke_delay_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct Clipboard {
+    /// Ask clipboard managers (e.g. Klipper, Windows clipboard history) not to remember the codes we paste
+    pub exclude_from_history: bool,
+}
+
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct Notifications {
+    /// Notify when codes are found that were not redeemed yet
+    pub new_codes: bool,
+    /// Notify how many codes were redeemed and which failed, and why a run failed
+    pub results: bool,
+}
+
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Serve {
+    /// Browser extensions that may use the control API, e.g. `chrome-extension://<id>`
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
@@ -34,6 +206,47 @@ pub struct Instructions {
 
 const CONFIG_FILE_NAME: &str = "config.json";
 
+impl Remote {
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        if !self.endpoints.is_empty() {
+            return self.endpoints.clone();
+        }
+
+        vec![Endpoint {
+            url: self.url.clone(),
+            max_retries: self.max_retries,
+            ..Endpoint::default()
+        }]
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            mode: InputMode::Paste,
+            keystroke_delay_ms: 20,
+        }
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Clipboard {
+            exclude_from_history: true,
+        }
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 1,
+            delay_ms: 2000,
+            final_pass: true,
+        }
+    }
+}
+
 pub fn dir() -> PathBuf {
     ProjectDirs::from("net", "liefland", "idle-champions-redeemer")
         .unwrap()
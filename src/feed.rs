@@ -0,0 +1,354 @@
+#![cfg(feature = "remote")]
+
+use licc::client::error::{ClientError, ErrorResponse, InnerErrorResponse};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// An entry as the endpoint lists it, before it is validated as a code.
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub code: String,
+    pub expired: bool,
+    pub expires_at: Option<String>,
+    pub creator: Option<String>,
+    pub creator_url: Option<String>,
+    /// Why the entry cannot be a code whatever it says, `code` is then what the endpoint listed
+    pub malformed: Option<&'static str>,
+}
+
+#[derive(Debug)]
+pub enum FeedError {
+    /// The request failed, or the server returned an error
+    Client(ClientError),
+    /// The response is not in the format we expected
+    Parse(String),
+}
+
+/// Feed
+/// A kind of endpoint codes can be retrieved from.
+pub(crate) trait Feed {
+    async fn entries(&self) -> Result<Vec<Entry>, FeedError>;
+}
+
+/// An `idle_champions_codes_api` instance.
+/// We read `/codes` ourselves rather than through licc, which panics on a response it cannot parse.
+pub struct Licc {
+    pub client: reqwest::Client,
+    /// Url of the `/codes` route
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CodesResponse {
+    codes: Vec<CodeResponse>,
+    #[serde(default)]
+    sources: HashMap<i32, SourceResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CodeResponse {
+    code: String,
+    expired: bool,
+    expires_at: Option<String>,
+    sources: Option<SourcesMapping>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourcesMapping {
+    creator: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourceResponse {
+    name: String,
+    #[serde(default)]
+    url: String,
+}
+
+impl Feed for Licc {
+    async fn entries(&self) -> Result<Vec<Entry>, FeedError> {
+        let body = get(&self.client, &self.url).await?;
+        let response: CodesResponse = serde_json::from_str(&body)
+            .map_err(|e| FeedError::Parse(format!("Invalid codes response: {}", e)))?;
+
+        Ok(response
+            .codes
+            .into_iter()
+            .map(|code| {
+                // Several codes can share a creator
+                let creator = code
+                    .sources
+                    .and_then(|sources| response.sources.get(&sources.creator));
+
+                Entry {
+                    creator: creator.map(|creator| creator.name.clone()),
+                    creator_url: creator
+                        .map(|creator| creator.url.clone())
+                        .filter(|url| !url.is_empty()),
+                    code: code.code,
+                    expired: code.expired,
+                    expires_at: code.expires_at,
+                    malformed: None,
+                }
+            })
+            .collect())
+    }
+}
+
+/// A JSON document, with JSON pointers (RFC 6901) to the list of codes and into each entry of it.
+pub struct Json {
+    pub client: reqwest::Client,
+    pub url: String,
+    /// Points at the list of codes, "" for a document that is a list
+    pub codes: String,
+    /// Points at the code in every entry, entries are the codes themselves if not set
+    pub code: Option<String>,
+    /// Points at the expiry (a timestamp or unix time) in every entry
+    pub expires_at: Option<String>,
+}
+
+impl Feed for Json {
+    async fn entries(&self) -> Result<Vec<Entry>, FeedError> {
+        let body = get(&self.client, &self.url).await?;
+        let document: Value = serde_json::from_str(&body)
+            .map_err(|e| FeedError::Parse(format!("Invalid JSON: {}", e)))?;
+
+        let list = document
+            .pointer(&self.codes)
+            .and_then(Value::as_array)
+            .ok_or_else(|| FeedError::Parse(format!("No list of codes at '{}'", self.codes)))?;
+
+        Ok(list
+            .iter()
+            .map(|item| {
+                let code = match &self.code {
+                    Some(pointer) => item.pointer(pointer),
+                    None => Some(item),
+                };
+                let (code, malformed) = match code {
+                    Some(Value::String(code)) => (code.clone(), None),
+                    Some(value) => (value.to_string(), Some("Not a string")),
+                    None => (item.to_string(), Some("No code in this entry")),
+                };
+
+                Entry {
+                    code,
+                    malformed,
+                    expires_at: self
+                        .expires_at
+                        .as_ref()
+                        .and_then(|pointer| item.pointer(pointer))
+                        .and_then(timestamp),
+                    ..Entry::default()
+                }
+            })
+            .collect())
+    }
+}
+
+/// A plain text list, one code per line. Blank lines and lines starting with '#' are ignored.
+pub struct Text {
+    pub client: reqwest::Client,
+    pub url: String,
+}
+
+impl Feed for Text {
+    async fn entries(&self) -> Result<Vec<Entry>, FeedError> {
+        let body = get(&self.client, &self.url).await?;
+
+        Ok(body
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| Entry {
+                code: line.to_string(),
+                ..Entry::default()
+            })
+            .collect())
+    }
+}
+
+/// An RSS or Atom feed, codes are picked out of the title and body of every item.
+#[cfg(feature = "feeds")]
+pub struct Rss {
+    pub client: reqwest::Client,
+    pub url: String,
+}
+
+#[cfg(feature = "feeds")]
+impl Feed for Rss {
+    async fn entries(&self) -> Result<Vec<Entry>, FeedError> {
+        let body = get(&self.client, &self.url).await?;
+        let document = roxmltree::Document::parse(&body)
+            .map_err(|e| FeedError::Parse(format!("Invalid feed: {}", e)))?;
+
+        let mut entries: Vec<Entry> = vec![];
+
+        // RSS calls them items, Atom calls them entries
+        for item in document
+            .descendants()
+            .filter(|node| matches!(node.tag_name().name(), "item" | "entry"))
+        {
+            let text = item
+                .children()
+                .filter(|node| {
+                    matches!(
+                        node.tag_name().name(),
+                        "title" | "description" | "summary" | "content" | "encoded"
+                    )
+                })
+                .flat_map(|node| node.descendants().filter_map(|n| n.text()))
+                .collect::<Vec<&str>>()
+                .join(" ");
+
+            for code in extract(&text) {
+                if !entries.iter().any(|entry| entry.code == code) {
+                    entries.push(Entry {
+                        code,
+                        ..Entry::default()
+                    });
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Pick codes out of free text, such as a feed item with HTML in it.
+/// Words that happen to be 12 or 16 letters long are not codes, so candidates are upper case, and undashed ones
+/// need a digit or symbol.
+/// Codes can contain symbols, which are only taken for punctuation around the code when the code is invalid with them.
+#[cfg(feature = "feeds")]
+fn extract(text: &str) -> Vec<String> {
+    const SYMBOLS: &str = "!@#$%^&*";
+
+    text.split(|c: char| !c.is_ascii_alphanumeric() && c != '-' && !SYMBOLS.contains(c))
+        .map(|token| token.trim_matches('-'))
+        .filter_map(|token| {
+            let trimmed = token.trim_matches(|c| SYMBOLS.contains(c));
+
+            [token, trimmed].into_iter().find(|token| {
+                let upper = token.chars().all(|c| !c.is_ascii_lowercase());
+                let distinctive = token.chars().any(|c| !c.is_ascii_alphabetic());
+
+                upper && distinctive && crate::code::Code::parse(token).is_ok()
+            })
+        })
+        .map(|token| token.to_string())
+        .collect()
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> Result<String, FeedError> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| FeedError::Client(ClientError::Reqwest(e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FeedError::Client(ClientError::ServerError(ErrorResponse {
+            error: InnerErrorResponse {
+                code: status.as_u16() as i32,
+                description: status.canonical_reason().unwrap_or_default().to_string(),
+                debug: None,
+            },
+        })));
+    }
+
+    response
+        .text()
+        .await
+        .map_err(|e| FeedError::Client(ClientError::Reqwest(e)))
+}
+
+/// Expiry as a string the remote module understands, unix timestamps are converted.
+fn timestamp(value: &Value) -> Option<String> {
+    match value {
+        Value::String(timestamp) => Some(timestamp.clone()),
+        Value::Number(number) => chrono::DateTime::from_timestamp(number.as_i64()?, 0)
+            .map(|datetime| datetime.to_rfc3339()),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "feeds"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Codes</title>
+  <item>
+    <title>New code: ABCD-EFGH-JKLM!</title>
+    <description><![CDATA[<p>Redeem (WXYZ-2345-6789) before Friday, or try "AB12CD34EF56".</p>]]></description>
+  </item>
+  <item><title>Maintenance tomorrow, no codes. Congratulations everyone</title></item>
+</channel></rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom"><title>Codes</title>
+  <entry>
+    <title>Weekly code, ABCD-EFGH-JKLM-NPQR.</title>
+    <content type="html">&lt;b&gt;Also:&lt;/b&gt; 1234ABCD5678, and ABCD-EFGH-JKLM again</content>
+  </entry>
+</feed>"#;
+
+    async fn entries(body: &str) -> Vec<String> {
+        let server = MockServer::start(vec![(200, body)]);
+        let feed = Rss {
+            client: reqwest::Client::new(),
+            url: server.url.clone(),
+        };
+
+        feed.entries()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.code)
+            .collect()
+    }
+
+    #[test]
+    fn extracts_dashed_and_undashed_codes() {
+        assert_eq!(
+            extract("Code: ABCD-EFGH-JKLM, or (AB12CD34EF56)."),
+            vec!["ABCD-EFGH-JKLM", "AB12CD34EF56"]
+        );
+        assert_eq!(
+            extract("\"ABCD-EFGH-JKLM-NPQR\"! Or ABCD-EFGH-JKLM!"),
+            vec!["ABCD-EFGH-JKLM-NPQR", "ABCD-EFGH-JKLM"]
+        );
+        assert_eq!(
+            extract("A code with a symbol: AB!D-EFGH-JKLM"),
+            vec!["AB!D-EFGH-JKLM"]
+        );
+    }
+
+    #[test]
+    fn ignores_words_of_code_length() {
+        assert!(extract("Congratulations, the INTERNATIONAL championship").is_empty());
+        assert!(
+            extract("An anti-inflammatory, counter-intuitive and Counter-Intuitive take")
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_rss_items() {
+        assert_eq!(
+            entries(RSS).await,
+            vec!["ABCD-EFGH-JKLM", "WXYZ-2345-6789", "AB12CD34EF56"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_atom_entries() {
+        assert_eq!(
+            entries(ATOM).await,
+            vec!["ABCD-EFGH-JKLM-NPQR", "1234ABCD5678", "ABCD-EFGH-JKLM"]
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{abort, config, err};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const DEFAULT_WINDOW_TITLE: &str = "Idle Champions";
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+/// WindowFocus
+/// Asks the window system which window currently has keyboard focus, and brings windows to the front.
+pub trait WindowFocus {
+    /// Title of the focused window, `None` if the window system cannot tell us.
+    fn active_window(&self) -> Result<Option<String>, &'static str>;
+
+    /// Attempt to focus the first window whose title contains `title`.
+    fn raise(&self, title: &str) -> Result<(), &'static str>;
+}
+
+/// FocusGuard
+/// Makes sure the game has focus before we send input, so codes don't end up typed into another window.
+pub struct FocusGuard {
+    backend: Box<dyn WindowFocus>,
+    title: String,
+    raise: bool,
+    /// How long to wait for the user to focus the game again
+    timeout: Duration,
+    /// Whether someone is around to focus the game, if not we give up straight away
+    interactive: bool,
+}
+
+impl FocusGuard {
+    pub fn new(config: Option<&config::Focus>, interactive: bool) -> Option<FocusGuard> {
+        let (title, raise, timeout_secs) = match config {
+            Some(focus) if !focus.check => return None,
+            Some(focus) => (focus.window_title.clone(), focus.raise, focus.timeout_secs),
+            None => (DEFAULT_WINDOW_TITLE.to_string(), false, None),
+        };
+
+        Some(FocusGuard {
+            backend: detect(),
+            title,
+            raise,
+            timeout: Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            interactive,
+        })
+    }
+
+    /// Whether the game has focus, `None` when the window system cannot tell us.
+    pub fn state(&self) -> Option<bool> {
+        match self.backend.active_window() {
+            Ok(Some(active)) => Some(active.contains(&self.title)),
+            Ok(None) | Err(_) => None,
+        }
+    }
+
+    /// Whether the game has focus, assumes it does when the window system cannot tell us.
+    pub fn is_focused(&self) -> bool {
+        self.state().unwrap_or(true)
+    }
+
+    /// Returns once the game has focus, raising it if configured to.
+    /// Returns `Ok(true)` if we had to wait, since the user may have moved the mouse in the meantime.
+    /// Fails if the game is not focused within the timeout, or right away when nobody is around to focus it.
+    pub fn ensure(&self) -> Result<bool, &'static str> {
+        if self.is_focused() {
+            return Ok(false);
+        }
+
+        if self.raise {
+            if let Err(e) = self.backend.raise(&self.title) {
+                err!("Failed to focus '{}': {}", self.title, e);
+            }
+
+            std::thread::sleep(Duration::from_millis(500));
+            if self.is_focused() {
+                return Ok(true);
+            }
+        }
+
+        if !self.interactive {
+            err!("'{}' is not focused", self.title);
+            return Err("Game window not focused");
+        }
+
+        err!(
+            "'{}' is not focused, pausing until it is focused again (up to {}s)..",
+            self.title,
+            self.timeout.as_secs()
+        );
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if abort::is_tripped() {
+                return Err(abort::reason());
+            }
+
+            if Instant::now() >= deadline {
+                err!("'{}' was not focused in time", self.title);
+                return Err("Game window not focused");
+            }
+
+            std::thread::sleep(Duration::from_millis(1000));
+
+            if self.is_focused() {
+                println!("'{}' is focused again, continuing.", self.title);
+                // Give the user a moment to let go of the mouse
+                std::thread::sleep(Duration::from_millis(1000));
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Pick a backend for the running session.
+pub fn detect() -> Box<dyn WindowFocus> {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return Box::new(Hyprland);
+    }
+
+    if std::env::var_os("SWAYSOCK").is_some() {
+        return Box::new(Sway);
+    }
+
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("DISPLAY").is_some() {
+        match x11::X11::connect() {
+            Ok(backend) => return Box::new(backend),
+            Err(e) => {
+                err!("{}, cannot detect window focus", e);
+            }
+        }
+    }
+
+    Box::new(Unsupported)
+}
+
+/// Platforms we cannot query, every window is assumed to be the right one.
+pub struct Unsupported;
+
+impl WindowFocus for Unsupported {
+    fn active_window(&self) -> Result<Option<String>, &'static str> {
+        Ok(None)
+    }
+
+    fn raise(&self, _title: &str) -> Result<(), &'static str> {
+        Err("Focusing windows is not supported on this platform")
+    }
+}
+
+/// Hyprland, queried through `hyprctl`.
+pub struct Hyprland;
+
+impl WindowFocus for Hyprland {
+    fn active_window(&self) -> Result<Option<String>, &'static str> {
+        let output = run("hyprctl", &["activewindow", "-j"])?;
+        let window: serde_json::Value =
+            serde_json::from_str(&output).map_err(|_| "Failed to parse hyprctl output")?;
+
+        Ok(window["title"].as_str().map(|title| title.to_string()))
+    }
+
+    fn raise(&self, title: &str) -> Result<(), &'static str> {
+        run(
+            "hyprctl",
+            &["dispatch", "focuswindow", &format!("title:{}", title)],
+        )
+        .map(|_| ())
+    }
+}
+
+/// Sway, queried through `swaymsg`.
+pub struct Sway;
+
+impl WindowFocus for Sway {
+    fn active_window(&self) -> Result<Option<String>, &'static str> {
+        let output = run("swaymsg", &["-t", "get_tree"])?;
+        let tree: serde_json::Value =
+            serde_json::from_str(&output).map_err(|_| "Failed to parse swaymsg output")?;
+
+        Ok(sway_focused(&tree))
+    }
+
+    fn raise(&self, title: &str) -> Result<(), &'static str> {
+        run("swaymsg", &[&format!("[title=\"{}\"] focus", title)]).map(|_| ())
+    }
+}
+
+fn sway_focused(node: &serde_json::Value) -> Option<String> {
+    if node["focused"].as_bool() == Some(true) {
+        return node["name"].as_str().map(|name| name.to_string());
+    }
+
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(sway_focused)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String, &'static str> {
+    let output = Command::new(program).args(args).output().map_err(|e| {
+        err!("Failed to run {}: {}", program, e);
+        "Failed to query the window manager"
+    })?;
+
+    if !output.status.success() {
+        return Err("The window manager returned an error");
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| "The window manager returned invalid UTF-8")
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use super::WindowFocus;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask};
+    use x11rb::rust_connection::RustConnection;
+
+    /// X11 (and XWayland), through the EWMH `_NET_ACTIVE_WINDOW` hint.
+    pub struct X11 {
+        conn: RustConnection,
+        root: u32,
+        net_active_window: Atom,
+        net_client_list: Atom,
+        net_wm_name: Atom,
+        utf8_string: Atom,
+    }
+
+    impl X11 {
+        pub fn connect() -> Result<X11, &'static str> {
+            let (conn, screen) = x11rb::connect(None).map_err(|_| "Failed to connect to X11")?;
+            let root = conn.setup().roots[screen].root;
+
+            let atom = |name: &[u8]| -> Result<Atom, &'static str> {
+                Ok(conn
+                    .intern_atom(false, name)
+                    .map_err(|_| "Failed to query X11")?
+                    .reply()
+                    .map_err(|_| "Failed to query X11")?
+                    .atom)
+            };
+
+            Ok(X11 {
+                net_active_window: atom(b"_NET_ACTIVE_WINDOW")?,
+                net_client_list: atom(b"_NET_CLIENT_LIST")?,
+                net_wm_name: atom(b"_NET_WM_NAME")?,
+                utf8_string: atom(b"UTF8_STRING")?,
+                conn,
+                root,
+            })
+        }
+
+        fn windows(&self, window: u32, property: Atom) -> Result<Vec<u32>, &'static str> {
+            let reply = self
+                .conn
+                .get_property(false, window, property, AtomEnum::WINDOW, 0, u32::MAX)
+                .map_err(|_| "Failed to query X11")?
+                .reply()
+                .map_err(|_| "Failed to query X11")?;
+
+            Ok(reply
+                .value32()
+                .map(|windows| windows.collect())
+                .unwrap_or_default())
+        }
+
+        fn title(&self, window: u32) -> Result<String, &'static str> {
+            let reply = self
+                .conn
+                .get_property(
+                    false,
+                    window,
+                    self.net_wm_name,
+                    self.utf8_string,
+                    0,
+                    u32::MAX,
+                )
+                .map_err(|_| "Failed to query X11")?
+                .reply()
+                .map_err(|_| "Failed to query X11")?;
+
+            Ok(String::from_utf8_lossy(&reply.value).to_string())
+        }
+    }
+
+    impl WindowFocus for X11 {
+        fn active_window(&self) -> Result<Option<String>, &'static str> {
+            match self.windows(self.root, self.net_active_window)?.first() {
+                Some(&window) if window != x11rb::NONE => Ok(Some(self.title(window)?)),
+                _ => Ok(None),
+            }
+        }
+
+        fn raise(&self, title: &str) -> Result<(), &'static str> {
+            let window = self
+                .windows(self.root, self.net_client_list)?
+                .into_iter()
+                .find(|&window| self.title(window).is_ok_and(|t| t.contains(title)))
+                .ok_or("No window with a matching title")?;
+
+            // Source indication 2 ("pager") asks the window manager to honour the request
+            let event = ClientMessageEvent::new(
+                32,
+                window,
+                self.net_active_window,
+                [2, x11rb::CURRENT_TIME, 0, 0, 0],
+            );
+
+            self.conn
+                .send_event(
+                    false,
+                    self.root,
+                    EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                    event,
+                )
+                .map_err(|_| "Failed to send X11 event")?;
+            self.conn.flush().map_err(|_| "Failed to send X11 event")?;
+
+            Ok(())
+        }
+    }
+}
@@ -1,11 +1,18 @@
+use crate::abort::Watchdog;
 use crate::cache::Cache;
+use crate::checkpoint::Checkpoint;
 use crate::clipboard::ClipboardIsolation;
-use crate::config::Instructions;
-use crate::{cache, err, progress, verbose};
+use crate::code::Code;
+use crate::config::{Clipboard, Input, InputMode, Instructions, Retry};
+use crate::focus::FocusGuard;
+use crate::notify::{self, Event, Notifier};
+use crate::summary::Summary;
+use crate::{abort, cache, err, progress, verbose};
 use enigo::{Keyboard, Mouse};
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::io::stdin;
+use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct Coordinates {
@@ -16,17 +23,26 @@ pub struct Coordinates {
 pub struct Interactor {
     enigo: enigo::Enigo,
     instructions: Instructions,
+    watchdog: Watchdog,
+    retry: Retry,
+    focus: Option<FocusGuard>,
+    input: Input,
+    clipboard: Option<ClipboardIsolation>,
+    clipboard_settings: Clipboard,
+    notifier: Option<Box<dyn Notifier>>,
+    /// Where to keep track of progress for `--resume`, only the main run has one
+    checkpoint_path: Option<PathBuf>,
+    summary: Summary,
+    // Where we last left the mouse, used to detect the user taking it back
+    last_location: Option<(i32, i32)>,
     slow: bool,
     verbose: bool,
 }
 
-const CHEST_CODE_LENGTH_SHORT: usize = 12;
-const CHEST_CODE_LENGTH_LONG: usize = 16;
-
 macro_rules! sleep_millis {
     ($milliseconds:expr, $slow:expr) => {
         if $slow {
-            std::thread::sleep(std::time::Duration::from_millis(($milliseconds + 500)));
+            std::thread::sleep(std::time::Duration::from_millis($milliseconds + 500));
         } else {
             std::thread::sleep(std::time::Duration::from_millis($milliseconds));
         }
@@ -37,6 +53,8 @@ macro_rules! action {
     ($self:ident, $action:expr, $fncall:stmt, $sleep:expr) => {
         verbose!($self, $action);
 
+        $self.ensure_focus()?;
+        $self.check_abort()?;
         $fncall
 
         sleep_millis!($sleep, $self.slow);
@@ -57,17 +75,18 @@ macro_rules! key {
     };
 }
 
+// Clicking the game is what focuses it, so unlike other actions a click does not wait for focus
 macro_rules! click {
     ($self:ident, $action:expr, $coords:expr, $sleep:expr) => {
-        action!(
-            $self,
-            $action,
-            $self.send_click($coords).map_err(|e| {
-                err!("Failed to send click: {}", e);
-                "Failed to send click"
-            })?,
-            $sleep
-        );
+        verbose!($self, $action);
+
+        $self.check_abort()?;
+        $self.send_click($coords).map_err(|e| {
+            err!("Failed to send click: {}", e);
+            "Failed to send click"
+        })?;
+
+        sleep_millis!($sleep, $self.slow);
     };
 }
 
@@ -83,12 +102,61 @@ impl Interactor {
                 "Failed to initialize enigo"
             })?,
             instructions,
+            watchdog: Watchdog::default(),
+            retry: Retry::default(),
+            focus: None,
+            input: Input::default(),
+            clipboard: None,
+            clipboard_settings: Clipboard::default(),
+            notifier: None,
+            checkpoint_path: None,
+            summary: Summary::new(),
+            last_location: None,
             verbose,
             slow,
         })
     }
 
-    pub fn redeem_many(&mut self, mut codes: Vec<String>) -> Result<(), Vec<String>> {
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Interactor {
+        self.watchdog = watchdog;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Retry) -> Interactor {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_focus(mut self, focus: Option<FocusGuard>) -> Interactor {
+        self.focus = focus;
+        self
+    }
+
+    pub fn with_input(mut self, input: Input) -> Interactor {
+        self.input = input;
+        self
+    }
+
+    pub fn with_clipboard(mut self, clipboard: Clipboard) -> Interactor {
+        self.clipboard_settings = clipboard;
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: Option<Box<dyn Notifier>>) -> Interactor {
+        self.notifier = notifier;
+        self
+    }
+
+    pub fn with_checkpoint(mut self, path: PathBuf) -> Interactor {
+        self.checkpoint_path = Some(path);
+        self
+    }
+
+    pub fn take_summary(&mut self) -> Summary {
+        std::mem::take(&mut self.summary)
+    }
+
+    pub fn redeem_many(&mut self, mut codes: Vec<Code>) -> Result<(), Vec<Code>> {
         if codes.is_empty() {
             return Ok(());
         }
@@ -99,9 +167,12 @@ impl Interactor {
             Cache::new()
         });
 
+        self.summary = Summary::new();
+
         codes.retain(|code| {
             if cache.contains(code) {
                 verbose!(self, "Skipping code '{}', already redeemed", code);
+                self.summary.skipped.push(code.clone());
                 return false;
             }
 
@@ -109,12 +180,16 @@ impl Interactor {
         });
 
         // Store mouse position
-        let (mouse_x, mouse_y) = self.enigo.location().map_err(|e| {
-            err!("Failed to get mouse position: {}", e);
-            vec![format!("Failed to get mouse position: {}", e)]
-        })?;
+        let (mouse_x, mouse_y) = match self.enigo.location() {
+            Ok(location) => location,
+            Err(e) => {
+                err!("Failed to get mouse position: {}", e);
+                return Err(codes);
+            }
+        };
+        self.last_location = Some((mouse_x, mouse_y));
 
-        let mut failed_codes: Vec<String> = vec![];
+        let mut failed_codes: Vec<Code> = vec![];
 
         let len = codes.len();
 
@@ -123,40 +198,122 @@ impl Interactor {
             println!("If you want to redeem them again, clear the cache file (--bust-cache) and try again.");
             return Ok(());
         } else {
-            println!("Redeeming {} codes: {}", len, codes.join(", "));
+            println!("Redeeming {} codes: {}", len, join(&codes));
         }
 
+        let mut checkpoint = Checkpoint::new(codes.clone());
+        self.save_checkpoint(&checkpoint);
+
+        self.watchdog.arm();
+        abort::reset();
+        abort::set_redeeming(true);
+        self.isolate_clipboard();
+
         let (progress_sender, _thread_handle) = progress::bar_create(len);
 
-        for code in codes {
+        let mut queue = codes.into_iter();
+        let mut aborted = false;
+        let mut redeemed: usize = 0;
+
+        for code in queue.by_ref() {
             progress_sender.send(format!("CODE {}", code)).ok();
 
-            if let Err(err) = self.redeem(&code) {
+            if let Err(err) = self.redeem_with_retries(&code) {
+                progress_sender.send("INC".to_string()).ok();
+
+                if abort::is_tripped() {
+                    aborted = true;
+                    failed_codes.push(code);
+                    break;
+                }
+
                 err!("Failed to redeem code '{}': {}", &code, err);
+                checkpoint.fail(&code);
+                self.save_checkpoint(&checkpoint);
                 failed_codes.push(code.clone());
-                progress_sender.send("INC".to_string()).ok();
                 sleep_millis!(100, self.slow);
                 continue;
             };
 
             progress_sender.send("INC".to_string()).ok();
+            redeemed += 1;
+            checkpoint.complete(&code);
+            self.save_checkpoint(&checkpoint);
             cache.push(code);
             // we need to wait for the chest animation to finish on success
             sleep_millis!(2600, self.slow);
         }
+
+        if !aborted && self.retry.final_pass && !failed_codes.is_empty() {
+            println!("Retrying {} failed code(s)", failed_codes.len());
+
+            for code in std::mem::take(&mut failed_codes) {
+                if aborted {
+                    failed_codes.push(code);
+                    continue;
+                }
+
+                progress_sender.send(format!("CODE {}", code)).ok();
+
+                if let Err(err) = self.attempt(&code) {
+                    aborted = abort::is_tripped();
+                    if !aborted {
+                        err!("Failed to redeem code '{}' on final retry: {}", &code, err);
+                    }
+                    failed_codes.push(code);
+                    continue;
+                }
+
+                redeemed += 1;
+                checkpoint.complete(&code);
+                self.save_checkpoint(&checkpoint);
+                cache.push(code);
+                sleep_millis!(2600, self.slow);
+            }
+        }
         progress_sender.send("FINISH".to_string()).ok();
+        // Dropping the isolation restores the clipboard
+        self.clipboard = None;
+
+        if aborted {
+            failed_codes.extend(queue);
+            err!(
+                "{}, stopped redeeming. {} code(s) were not redeemed: {}",
+                abort::reason(),
+                failed_codes.len(),
+                join(&failed_codes)
+            );
+            self.release_modifiers();
+            println!("Redeemed {} of {} codes before stopping.", redeemed, len);
+            if self.checkpoint_path.is_some() {
+                println!("Run icredeem --resume to pick up where you left off.");
+            }
+        }
 
-        if !failed_codes.is_empty() {
-            return Err(failed_codes);
+        self.summary.failed = failed_codes.clone();
+
+        notify::send(
+            self.notifier.as_deref(),
+            &Event::finished(&self.summary, len),
+        );
+
+        if let Some(path) = self
+            .checkpoint_path
+            .as_ref()
+            .filter(|_| checkpoint.is_done())
+        {
+            if let Err(e) = Checkpoint::remove(path) {
+                err!("{}", e);
+            }
         }
 
         // Reset mouse position
-        self.enigo
+        if let Err(e) = self
+            .enigo
             .move_mouse(mouse_x, mouse_y, enigo::Coordinate::Abs)
-            .map_err(|e| {
-                err!("Failed to move mouse: {}", e);
-                vec![format!("Failed to move mouse: {}", e)]
-            })?;
+        {
+            err!("Failed to move mouse: {}", e);
+        }
         #[cfg(feature = "cache")]
         match cache.write(&cache_path) {
             Ok(_) => {
@@ -166,19 +323,82 @@ impl Interactor {
                 err!("Failed to write cache to file: {}", e);
             }
         };
+        // Only now, an interrupt before the cache is written would forget the codes redeemed in this batch
+        abort::set_redeeming(false);
+
+        if !failed_codes.is_empty() {
+            return Err(failed_codes);
+        }
 
         Ok(())
     }
 
-    pub fn redeem(&mut self, code: &str) -> Result<(), String> {
-        let normalized_code = self.normalize(code)?;
+    fn redeem_with_retries(&mut self, code: &Code) -> Result<(), String> {
+        let mut delay = self.retry.delay_ms;
+        let mut result = self.attempt(code);
+
+        for _ in 0..self.retry.attempts {
+            if result.is_ok() || abort::is_tripped() {
+                break;
+            }
+
+            verbose!(self, "Retrying code '{}' in {}ms", code, delay);
+            std::thread::sleep(std::time::Duration::from_millis(delay));
+            delay = delay.saturating_mul(2);
+
+            result = self.attempt(code);
+        }
+
+        result
+    }
+
+    // A single try at redeeming a code, recorded in the summary
+    fn attempt(&mut self, code: &Code) -> Result<(), String> {
+        let result = self.redeem(code);
+
+        match &result {
+            Ok(_) => {
+                self.summary.record(code, None);
+                self.summary.redeemed.push(code.clone());
+            }
+            Err(err) => {
+                self.summary.record(code, Some(err.clone()));
+            }
+        }
+
+        result
+    }
+
+    pub fn redeem(&mut self, code: &Code) -> Result<(), String> {
+        // Outside of redeem_many (e.g. the setup demo), the clipboard is isolated for just this code
+        let standalone = self.clipboard.is_none();
+        if standalone {
+            self.isolate_clipboard();
+        }
+
+        let result = self.redeem_code(code);
+
+        if standalone {
+            self.clipboard = None;
+        }
+
+        result
+    }
+
+    fn redeem_code(&mut self, code: &Code) -> Result<(), String> {
         let instructions = self.instructions;
+        let mut input_mode = self.input.mode;
 
         #[cfg(not(feature = "progress"))]
-        println!("Redeeming code '{}'", &normalized_code);
+        println!("Redeeming code '{}'", code);
+        verbose!(self, "Redeeming {} code '{}'", code.kind(), code);
 
-        // Isolate the clipboard to prevent interference, it implements Drop and will restore the clipboard when it goes out of scope
-        let _cb_isolation = ClipboardIsolation::isolate(normalized_code, self.verbose)?;
+        if let Some(clipboard) = self.clipboard.as_mut() {
+            if let Err(e) = clipboard.swap(code.as_str()) {
+                err!("{}, typing the code instead", e);
+                input_mode = InputMode::Type;
+            }
+        }
 
         click!(
             self,
@@ -186,7 +406,14 @@ impl Interactor {
             &instructions.unlock_chest,
             2500
         );
-        action!(self, "Pasting the code", self.paste_clipboard()?, 1500);
+        match input_mode {
+            InputMode::Paste => {
+                action!(self, "Pasting the code", self.paste_clipboard()?, 1500);
+            }
+            InputMode::Type => {
+                action!(self, "Typing the code", self.type_code(code)?, 1500);
+            }
+        }
 
         // this animation takes forever if successful
         // which is the whole reason i wrote this software in the first place
@@ -210,25 +437,6 @@ impl Interactor {
         Ok(())
     }
 
-    fn normalize(&self, code: &str) -> Result<String, String> {
-        let normalized = code.replace('-', "");
-
-        self.validate(&normalized)?;
-
-        Ok(normalized)
-    }
-
-    fn validate(&self, code: &str) -> Result<(), String> {
-        if code.len() != CHEST_CODE_LENGTH_SHORT && code.len() != CHEST_CODE_LENGTH_LONG {
-            return Err(format!(
-                "Code must be {} or {} characters long",
-                CHEST_CODE_LENGTH_SHORT, CHEST_CODE_LENGTH_LONG
-            ));
-        }
-
-        Ok(())
-    }
-
     pub fn send_click(&mut self, coords: &Coordinates) -> Result<(), &'static str> {
         verbose!(self, "==> Sending CLICK at X:{}, Y:{}", coords.x, coords.y);
 
@@ -238,6 +446,7 @@ impl Interactor {
                 err!("Failed to move mouse: {}", e);
                 "Failed to move mouse"
             })?;
+        self.last_location = Some((coords.x, coords.y));
 
         sleep_millis!(10, false); // Probably not needed
 
@@ -251,6 +460,69 @@ impl Interactor {
         Ok(())
     }
 
+    // Isolate the clipboard to prevent interference, it implements Drop and will restore the clipboard when it goes out of scope
+    fn isolate_clipboard(&mut self) {
+        if self.input.mode != InputMode::Paste {
+            return;
+        }
+
+        match ClipboardIsolation::isolate(
+            self.verbose,
+            self.clipboard_settings.exclude_from_history,
+        ) {
+            Ok(isolation) => self.clipboard = Some(isolation),
+            Err(e) => {
+                err!("{}, typing codes instead", e);
+                self.input.mode = InputMode::Type;
+            }
+        }
+    }
+
+    fn save_checkpoint(&self, checkpoint: &Checkpoint) {
+        let Some(path) = &self.checkpoint_path else {
+            return;
+        };
+
+        match checkpoint.write(path) {
+            Ok(_) => {
+                verbose!(self, "Checkpoint written to file");
+            }
+            Err(e) => {
+                err!("{}", e);
+            }
+        }
+    }
+
+    fn ensure_focus(&mut self) -> Result<(), &'static str> {
+        let Some(focus) = &self.focus else {
+            return Ok(());
+        };
+
+        if focus.ensure()? {
+            // The user likely used the mouse to focus the game, don't mistake that for an abort
+            self.last_location = self.enigo.location().ok();
+        }
+
+        Ok(())
+    }
+
+    fn check_abort(&mut self) -> Result<(), &'static str> {
+        // Not every platform can tell us where the mouse is, in which case only the abort key applies
+        let location = self.enigo.location().ok();
+
+        self.watchdog.check(self.last_location, location)
+    }
+
+    // Let go of anything we may have been holding down when interrupted
+    fn release_modifiers(&mut self) {
+        if let Err(e) = self
+            .enigo
+            .key(enigo::Key::Control, enigo::Direction::Release)
+        {
+            err!("Failed to release key: {}", e);
+        }
+    }
+
     fn send_keyclick(&mut self, key_press: enigo::Key) -> Result<(), &'static str> {
         verbose!(self, "==> Sending KEY '{:?}'", key_press);
 
@@ -264,6 +536,23 @@ impl Interactor {
         Ok(())
     }
 
+    fn type_code(&mut self, code: &Code) -> Result<(), &'static str> {
+        verbose!(self, "==> Typing code");
+
+        for c in code.as_str().chars() {
+            self.enigo
+                .key(enigo::Key::Unicode(c), enigo::Direction::Click)
+                .map_err(|e| {
+                    err!("Failed to press key: {}", e);
+                    "Failed to press key"
+                })?;
+
+            sleep_millis!(self.input.keystroke_delay_ms, false);
+        }
+
+        Ok(())
+    }
+
     fn paste_clipboard(&mut self) -> Result<(), &'static str> {
         verbose!(self, "==> Pasting clipboard");
 
@@ -293,6 +582,14 @@ impl Interactor {
     }
 }
 
+pub fn join(codes: &[Code]) -> String {
+    codes
+        .iter()
+        .map(|code| code.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 pub fn await_enter() {
     let mut s = String::new();
     stdin().read_line(&mut s).expect("Failed to read line");
@@ -1,19 +1,39 @@
 use crate::app::{run, RunInstructions};
-use crate::cli::ConfigCommand;
+use crate::cli::{ConfigCommand, QueueCommand, ScheduleCommand};
 use crate::interaction::await_enter;
 use crate::setup::{is_setup, setup};
+mod abort;
 mod app;
 mod cache;
+mod checkpoint;
 mod cli;
 mod clipboard;
+mod code;
 mod config;
+mod feed;
+mod focus;
 mod interaction;
 mod macros;
+mod mock;
+mod notify;
+mod offline;
 mod progress;
+mod queue;
 mod remote;
+mod report;
+mod schedule;
+mod serve;
 mod setup;
+mod source;
+mod summary;
+mod watch;
+mod webhook;
 
 fn main() -> Result<(), &'static str> {
+    if let Err(e) = abort::handle_signals() {
+        err!("{}", e);
+    }
+
     let matches = match cli::parser() {
         Ok(m) => m,
         Err(e) => {
@@ -46,6 +66,74 @@ fn main() -> Result<(), &'static str> {
                     std::process::exit(ExitCode::ConfigFailed.into());
                 }
             },
+            ConfigCommand::Queue { action } => {
+                let result = match action {
+                    QueueCommand::Add { codes } => Some(queue::add(codes)),
+                    QueueCommand::List => Some(queue::list()),
+                    QueueCommand::Remove { codes } => Some(queue::remove(codes)),
+                    QueueCommand::Clear => Some(queue::clear()),
+                    // Redeeming needs the setup and the config, it happens below
+                    QueueCommand::Run => None,
+                };
+
+                match result {
+                    Some(Ok(_)) => std::process::exit(ExitCode::Success.into()),
+                    Some(Err(e)) => {
+                        err!("{}", e);
+                        std::process::exit(ExitCode::QueueFailed.into());
+                    }
+                    None => {}
+                }
+            }
+            ConfigCommand::Schedule { action } => {
+                let result = match action {
+                    ScheduleCommand::Install { daily } => {
+                        check_setup(&matches);
+                        schedule::install(daily)
+                    }
+                    ScheduleCommand::Status => schedule::status(),
+                    ScheduleCommand::Remove => schedule::remove(),
+                };
+
+                match result {
+                    Ok(_) => std::process::exit(ExitCode::Success.into()),
+                    Err(e) => {
+                        err!("{}", e);
+                        std::process::exit(ExitCode::ScheduleFailed.into());
+                    }
+                }
+            }
+            #[cfg(feature = "remote")]
+            ConfigCommand::Submit {
+                code,
+                expires,
+                creator,
+                creator_url,
+            } => {
+                let config = config::read().unwrap_or_else(|e| {
+                    err!("{}", e);
+                    std::process::exit(ExitCode::ConfigFailed.into());
+                });
+
+                let submission = remote::Submission {
+                    code,
+                    expires_at: expires,
+                    creator,
+                    creator_url,
+                };
+
+                match app::submit(&config, submission) {
+                    Ok(_) => std::process::exit(ExitCode::Success.into()),
+                    Err(e) => {
+                        err!("{}", e);
+                        std::process::exit(ExitCode::SubmitFailed.into());
+                    }
+                }
+            }
+            #[cfg(feature = "remote")]
+            ConfigCommand::Watch { .. } => {}
+            #[cfg(feature = "serve")]
+            ConfigCommand::Serve { .. } => {}
         }
     }
 
@@ -81,8 +169,61 @@ fn main() -> Result<(), &'static str> {
         println!("Cache busted successfully!");
     }
 
+    if let Some(ConfigCommand::Queue { .. }) = matches.config {
+        if !matches.no_interaction {
+            println!("Ensure you are on the Chest menu (default hotkey 'o'), and press ENTER to start redemption.");
+            await_enter();
+        }
+
+        let webhooks = webhook::Webhooks::from_config(&config);
+
+        match queue::run(app::Settings::from(&matches, &config), webhooks) {
+            Ok(_) => std::process::exit(ExitCode::Success.into()),
+            Err(e) => {
+                err!("{}", e);
+                std::process::exit(ExitCode::run_failed().into());
+            }
+        }
+    }
+
+    #[cfg(feature = "serve")]
+    if let Some(ConfigCommand::Serve { port }) = matches.config {
+        let webhooks = webhook::Webhooks::from_config(&config);
+        let origins = config.serve.clone().unwrap_or_default().allowed_origins;
+
+        match serve::serve(
+            app::Settings::from(&matches, &config),
+            webhooks,
+            port,
+            origins,
+        ) {
+            Ok(_) => std::process::exit(ExitCode::Success.into()),
+            Err(e) => {
+                err!("{}", e);
+                std::process::exit(ExitCode::run_failed().into());
+            }
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    if let Some(ConfigCommand::Watch { interval, confirm }) = matches.config {
+        let mut matches = matches;
+        // Watching only makes sense with a remote to watch
+        matches.prefer_remote = true;
+
+        match watch::watch(RunInstructions::create(matches, config), interval, confirm) {
+            Ok(_) => std::process::exit(ExitCode::Success.into()),
+            Err(e) => {
+                err!("{}", e);
+                std::process::exit(ExitCode::run_failed().into());
+            }
+        }
+    }
+
     if matches.codes.is_empty()
-        && matches.url.is_none()
+        && matches.url.is_empty()
+        && matches.file.is_empty()
+        && !matches.resume
         && config.default_strategy == config::Strategy::Local
     {
         err!("No codes provided");
@@ -98,7 +239,7 @@ fn main() -> Result<(), &'static str> {
         Ok(_) => {}
         Err(e) => {
             err!("{}", e);
-            std::process::exit(ExitCode::RunFailed.into());
+            std::process::exit(ExitCode::run_failed().into());
         }
     }
 
@@ -151,6 +292,22 @@ enum ExitCode {
     ConfigFailed = 4,
     LocalRunFailed = 5,
     RunFailed = 7,
+    #[allow(dead_code)] // Can be dead code if the feature is not enabled
+    SubmitFailed = 8,
+    ScheduleFailed = 9,
+    QueueFailed = 10,
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    /// `RunFailed`, or `Interrupted` when the run stopped because the user interrupted it.
+    fn run_failed() -> ExitCode {
+        if abort::is_interrupted() {
+            ExitCode::Interrupted
+        } else {
+            ExitCode::RunFailed
+        }
+    }
 }
 
 impl From<ExitCode> for i32 {
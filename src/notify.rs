@@ -0,0 +1,179 @@
+use crate::code::Code;
+use crate::summary::Summary;
+use crate::{config, err};
+
+/// Something worth telling the user about when they are not watching the terminal.
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+pub enum Event<'a> {
+    /// Codes appeared that have not been redeemed yet
+    NewCodes(&'a [Code]),
+    /// A redemption finished, `failed` lists the codes that were not redeemed
+    Finished {
+        redeemed: usize,
+        total: usize,
+        failed: &'a [Code],
+    },
+    /// A run could not be completed, e.g. because the codes could not be retrieved or it was aborted
+    Failed(&'a str),
+}
+
+impl<'a> Event<'a> {
+    /// The event for a redemption of `total` codes that ended with `summary`.
+    pub fn finished(summary: &'a Summary, total: usize) -> Event<'a> {
+        Event::Finished {
+            redeemed: summary.redeemed.len(),
+            total,
+            failed: &summary.failed,
+        }
+    }
+}
+
+/// Notifier
+/// Delivers events to the user, outside of the terminal.
+pub trait Notifier {
+    fn notify(&self, event: &Event) -> Result<(), &'static str>;
+}
+
+/// Deliver `event` if there is a notifier, failing to notify does not fail the run.
+pub fn send(notifier: Option<&dyn Notifier>, event: &Event) {
+    if let Some(Err(e)) = notifier.map(|notifier| notifier.notify(event)) {
+        err!("{}", e);
+    }
+}
+
+/// The notifier configured, `None` if notifications are not enabled.
+#[cfg(feature = "notifications")]
+pub fn from_config(notifications: Option<&config::Notifications>) -> Option<Box<dyn Notifier>> {
+    let notifications = notifications.filter(|n| n.new_codes || n.results)?;
+
+    Some(Box::new(Desktop {
+        settings: *notifications,
+    }))
+}
+
+#[cfg(not(feature = "notifications"))]
+pub fn from_config(notifications: Option<&config::Notifications>) -> Option<Box<dyn Notifier>> {
+    if notifications.is_some_and(|n| n.new_codes || n.results) {
+        err!("Notifications are enabled, but the notifications feature is not");
+    }
+
+    None
+}
+
+/// Desktop notifications, through D-Bus on Linux and the native notification center elsewhere.
+#[cfg(feature = "notifications")]
+pub struct Desktop {
+    settings: config::Notifications,
+}
+
+#[cfg(feature = "notifications")]
+impl Notifier for Desktop {
+    fn notify(&self, event: &Event) -> Result<(), &'static str> {
+        let Some((summary, body)) = message(&self.settings, event) else {
+            return Ok(());
+        };
+
+        notify_rust::Notification::new()
+            .appname("icredeem")
+            .summary(&summary)
+            .body(&body)
+            .show()
+            .map(|_| ())
+            .map_err(|_| "Failed to show a desktop notification")
+    }
+}
+
+/// The summary and body of the notification for `event`, `None` if the user did not ask to be told about it.
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+fn message(settings: &config::Notifications, event: &Event) -> Option<(String, String)> {
+    match event {
+        Event::NewCodes(codes) if settings.new_codes => Some((
+            format!("{} new code(s) available", codes.len()),
+            crate::interaction::join(codes),
+        )),
+        Event::Finished {
+            redeemed,
+            total,
+            failed,
+        } if settings.results => {
+            let summary = format!("Redeemed {}/{} code(s)", redeemed, total);
+
+            if failed.is_empty() {
+                Some((summary, String::new()))
+            } else {
+                Some((
+                    summary,
+                    format!("Failed: {}", crate::interaction::join(failed)),
+                ))
+            }
+        }
+        Event::Failed(reason) if settings.results => {
+            Some(("icredeem failed".to_string(), reason.to_string()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(code: &str) -> Code {
+        Code::parse(code).unwrap()
+    }
+
+    fn settings(new_codes: bool, results: bool) -> config::Notifications {
+        config::Notifications { new_codes, results }
+    }
+
+    fn text(summary: &str, body: &str) -> Option<(String, String)> {
+        Some((summary.to_string(), body.to_string()))
+    }
+
+    #[test]
+    fn finished_counts_redeemed_codes_out_of_those_tried() {
+        let mut summary = Summary::new();
+        summary.redeemed = vec![code("AAAA-AAAA-AAAA"), code("BBBB-BBBB-BBBB")];
+        // Skipped codes were already redeemed, and are not part of the total
+        summary.skipped = vec![code("CCCC-CCCC-CCCC")];
+        summary.failed = vec![code("DDDD-DDDD-DDDD")];
+
+        assert_eq!(
+            message(&settings(true, true), &Event::finished(&summary, 3)),
+            text("Redeemed 2/3 code(s)", "Failed: DDDD-DDDD-DDDD")
+        );
+
+        summary.failed.clear();
+        assert_eq!(
+            message(&settings(true, true), &Event::finished(&summary, 2)),
+            text("Redeemed 2/2 code(s)", "")
+        );
+    }
+
+    #[test]
+    fn new_codes_follow_their_toggle() {
+        let codes = [code("AAAA-AAAA-AAAA"), code("BBBB-BBBB-BBBB")];
+        let event = Event::NewCodes(&codes);
+
+        assert_eq!(
+            message(&settings(true, false), &event),
+            text("2 new code(s) available", "AAAA-AAAA-AAAA, BBBB-BBBB-BBBB")
+        );
+        assert_eq!(message(&settings(false, true), &event), None);
+    }
+
+    #[test]
+    fn results_follow_their_toggle() {
+        let summary = Summary::new();
+        let finished = Event::finished(&summary, 0);
+        let failed = Event::Failed("Failed to retrieve codes");
+
+        assert!(message(&settings(false, true), &finished).is_some());
+        assert_eq!(
+            message(&settings(false, true), &failed),
+            text("icredeem failed", "Failed to retrieve codes")
+        );
+        assert_eq!(message(&settings(true, false), &finished), None);
+        assert_eq!(message(&settings(true, false), &failed), None);
+    }
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "remote")]
+
+use crate::config::Endpoint;
+use crate::remote::RemoteCode;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Key for endpoints without a url, which use the public instance
+const PUBLIC_INSTANCE: &str = "default";
+
+/// OfflineCodes
+/// The codes last retrieved from every endpoint, used when the endpoints cannot be reached or with `--offline`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct OfflineCodes {
+    endpoints: HashMap<String, Retrieval>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Retrieval {
+    pub retrieved_at: DateTime<Utc>,
+    pub codes: Vec<RemoteCode>,
+}
+
+pub fn path() -> PathBuf {
+    crate::config::dir().join("remote_codes.json")
+}
+
+impl OfflineCodes {
+    pub fn from_file(path: &PathBuf) -> Result<OfflineCodes, &'static str> {
+        if !path.exists() {
+            return Ok(OfflineCodes::default());
+        }
+
+        let jsn =
+            std::fs::read_to_string(path).map_err(|_| "Failed to read stored remote codes")?;
+
+        serde_json::from_str(&jsn).map_err(|_| "Failed to parse stored remote codes")
+    }
+
+    pub fn store(&mut self, endpoint: &Endpoint, codes: &[RemoteCode]) {
+        self.endpoints.insert(
+            key(endpoint),
+            Retrieval {
+                retrieved_at: Utc::now(),
+                codes: codes.to_vec(),
+            },
+        );
+    }
+
+    /// What we know about the given endpoints, the most recent retrieval wins.
+    /// With `union` every endpoint's codes are combined, dated by the oldest retrieval.
+    pub fn lookup(&self, endpoints: &[Endpoint], union: bool) -> Option<Retrieval> {
+        let retrievals: Vec<&Retrieval> = endpoints
+            .iter()
+            .filter_map(|endpoint| self.endpoints.get(&key(endpoint)))
+            .collect();
+
+        if !union {
+            return retrievals
+                .into_iter()
+                .max_by_key(|r| r.retrieved_at)
+                .cloned();
+        }
+
+        Some(Retrieval {
+            retrieved_at: retrievals.iter().map(|r| r.retrieved_at).min()?,
+            codes: retrievals
+                .iter()
+                .flat_map(|r| r.codes.iter().cloned())
+                .collect(),
+        })
+    }
+
+    pub fn write(&self, path: &PathBuf) -> Result<(), &'static str> {
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap())
+                .map_err(|_| "Failed to create config directory")?;
+        }
+
+        let jsn =
+            serde_json::to_string(&self).map_err(|_| "Failed to serialize stored remote codes")?;
+
+        std::fs::write(path, jsn).map_err(|_| "Failed to write stored remote codes")
+    }
+}
+
+impl Retrieval {
+    /// How long ago the codes were retrieved, e.g. "3d 4h" or "25m".
+    pub fn age(&self) -> String {
+        let age = Utc::now() - self.retrieved_at;
+
+        if age >= Duration::days(1) {
+            format!("{}d {}h", age.num_days(), age.num_hours() % 24)
+        } else if age >= Duration::hours(1) {
+            format!("{}h {}m", age.num_hours(), age.num_minutes() % 60)
+        } else {
+            format!("{}m", age.num_minutes())
+        }
+    }
+}
+
+fn key(endpoint: &Endpoint) -> String {
+    endpoint
+        .url
+        .clone()
+        .unwrap_or_else(|| PUBLIC_INSTANCE.to_string())
+}
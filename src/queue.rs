@@ -0,0 +1,201 @@
+use crate::app::{interactor, Settings};
+use crate::cache::{self, Cache};
+use crate::code::Code;
+use crate::err;
+use crate::interaction::join;
+use crate::webhook::Webhooks;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Queue
+/// Codes collected throughout the day, waiting to be redeemed in one sitting with `icredeem queue run`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Queue {
+    pub codes: Vec<Code>,
+}
+
+pub fn path() -> PathBuf {
+    crate::config::dir().join("queue.json")
+}
+
+impl Queue {
+    pub fn from_file(path: &PathBuf) -> Result<Queue, &'static str> {
+        if !path.exists() {
+            return Ok(Queue::default());
+        }
+
+        let jsn = std::fs::read_to_string(path).map_err(|_| "Failed to read queue file")?;
+
+        serde_json::from_str(&jsn).map_err(|_| "Failed to parse queue file")
+    }
+
+    /// Queue a code, returns `false` if it was already queued.
+    pub fn add(&mut self, code: Code) -> bool {
+        if self.codes.contains(&code) {
+            return false;
+        }
+
+        self.codes.push(code);
+        true
+    }
+
+    /// Remove a code, returns `false` if it was not queued.
+    pub fn remove(&mut self, code: &Code) -> bool {
+        let len = self.codes.len();
+        self.codes.retain(|c| c != code);
+
+        self.codes.len() != len
+    }
+
+    /// Write the queue through a temporary file, so an interrupted write never leaves a truncated queue behind.
+    pub fn write(&self, path: &PathBuf) -> Result<(), &'static str> {
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap())
+                .map_err(|_| "Failed to create config directory")?;
+        }
+
+        let jsn = serde_json::to_string(&self).map_err(|_| "Failed to serialize queue")?;
+
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, jsn).map_err(|_| "Failed to write queue file")?;
+        std::fs::rename(&temporary, path).map_err(|_| "Failed to write queue file")
+    }
+}
+
+/// Queue codes, skipping codes that were already redeemed or queued.
+pub fn add(codes: Vec<Code>) -> Result<(), &'static str> {
+    let path = path();
+    let mut queue = Queue::from_file(&path)?;
+    let cache = Cache::from_file(&cache::path()).unwrap_or_else(|e| {
+        err!("Failed to read cache from file: {}", e);
+        Cache::new()
+    });
+
+    for code in codes {
+        if cache.contains(&code) {
+            println!("Skipping {}, already redeemed", code);
+        } else if !queue.add(code.clone()) {
+            println!("Skipping {}, already queued", code);
+        } else {
+            println!("Queued {}", code);
+        }
+    }
+
+    queue.write(&path)
+}
+
+pub fn list() -> Result<(), &'static str> {
+    let queue = Queue::from_file(&path())?;
+
+    if queue.codes.is_empty() {
+        println!("The queue is empty.");
+        return Ok(());
+    }
+
+    println!("{} queued code(s):", queue.codes.len());
+    for code in &queue.codes {
+        println!("  {}", code);
+    }
+
+    Ok(())
+}
+
+pub fn remove(codes: Vec<Code>) -> Result<(), &'static str> {
+    let path = path();
+    let mut queue = Queue::from_file(&path)?;
+
+    for code in codes {
+        if queue.remove(&code) {
+            println!("Removed {}", code);
+        } else {
+            err!("{} is not queued", code);
+        }
+    }
+
+    queue.write(&path)
+}
+
+pub fn clear() -> Result<(), &'static str> {
+    Queue::default().write(&path())?;
+    println!("The queue is empty.");
+
+    Ok(())
+}
+
+/// Redeem every queued code. Redeemed codes, and codes that turn out to be redeemed already, leave the queue,
+/// failed codes stay queued for the next run.
+pub fn run(settings: Settings, webhooks: Option<Webhooks>) -> Result<(), &'static str> {
+    let path = path();
+    let queue = Queue::from_file(&path)?;
+
+    if queue.codes.is_empty() {
+        println!("The queue is empty, add codes with `icredeem queue add`.");
+        return Ok(());
+    }
+
+    let mut interactor = interactor(settings)?;
+    let result = interactor.redeem_many(queue.codes.clone());
+
+    let summary = interactor.take_summary();
+    if !summary.attempts.is_empty() {
+        println!("{}", summary);
+    }
+
+    if let Some(webhooks) = webhooks.as_ref().filter(|_| summary.has_codes()) {
+        webhooks.send(&summary);
+    }
+
+    // Codes may have been queued or removed while we were redeeming, so start from the queue as it is now
+    let mut queue = Queue::from_file(&path)?;
+    for code in summary.redeemed.iter().chain(summary.skipped.iter()) {
+        queue.remove(code);
+    }
+    queue.write(&path)?;
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(failed_codes) => {
+            err!(
+                "Failed to redeem codes, they stay queued: {}",
+                join(&failed_codes)
+            );
+            Err("Failed to redeem at least 1 code")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(code: &str) -> Code {
+        Code::parse(code).unwrap()
+    }
+
+    #[test]
+    fn writes_and_reads_back() {
+        let dir = std::env::temp_dir().join(format!("icredeem-queue-{}", std::process::id()));
+        let path = dir.join("queue.json");
+
+        let mut queue = Queue::default();
+        assert!(queue.add(code("AAAA-AAAA-AAAA")));
+        assert!(!queue.add(code("AAAA-AAAA-AAAA")));
+        assert!(queue.add(code("BBBB-BBBB-BBBB")));
+        queue.write(&path).unwrap();
+
+        let mut read = Queue::from_file(&path).unwrap();
+        assert_eq!(read.codes, queue.codes);
+        assert!(read.remove(&code("AAAA-AAAA-AAAA")));
+        assert!(!read.remove(&code("AAAA-AAAA-AAAA")));
+        read.write(&path).unwrap();
+
+        assert_eq!(
+            Queue::from_file(&path).unwrap().codes,
+            vec![code("BBBB-BBBB-BBBB")]
+        );
+        // Nothing is left behind next to the queue
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,31 +1,493 @@
 #![cfg(feature = "remote")]
 
+use crate::code::Code;
+use crate::config::{Endpoint, Format, Http, Remote};
 use crate::err;
+use crate::feed::{self, Entry, Feed, FeedError};
+use crate::offline::{self, OfflineCodes};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use licc::client::error::ClientError;
-use std::cmp::min;
+use licc::client::CodesClient;
+use licc::write::{InsertCodeRequest, SourceLookup};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 
+/// RemoteCode
+/// A code retrieved from the remote, along with what the remote knows about it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCode {
+    pub code: Code,
+    pub expired: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub creator: Option<String>,
+    #[serde(default)]
+    pub creator_url: Option<String>,
+    /// Url of the endpoint the code was retrieved from, `None` for the public instance
+    pub endpoint: Option<String>,
+}
+
+/// Retrieved
+/// What the endpoints returned, split into valid codes and the entries that are not valid codes.
+#[derive(Debug, Default)]
+pub struct Retrieved {
+    pub codes: Vec<RemoteCode>,
+    pub rejected: Vec<Rejected>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rejected {
+    pub code: String,
+    pub endpoint: Option<String>,
+}
+
+const MAX_ATTEMPTS: u8 = 5;
+// The public instance, used for endpoints without a url
+const PUBLIC_INSTANCE_URL: &str = "https://codes.idlechampions.liefland.net/v1";
+// The API requires an expiry, and suggests next week when it is not known
+const DEFAULT_SUBMISSION_EXPIRY_DAYS: i64 = 7;
+pub(crate) const UNKNOWN_CREATOR: &str = "Unknown";
+const DEFAULT_MAX_TOTAL_SECS: u64 = 60;
+// Offline codes older than this come with a warning
+const STALE_AFTER_HOURS: i64 = 24;
+
+/// Retrieve codes from the endpoints, or from the codes they last returned if none can be reached.
+/// With `offline`, only the codes they last returned are used.
 pub async fn get_codes(
-    client: licc::client::CodesClient,
-    mut max_retries: u8,
-) -> Result<Vec<licc::Code>, &'static str> {
-    // avoid significant server flood
-    max_retries = min(5, max_retries);
-
-    for i in 0..max_retries {
-        let result = client.get_codes_slim().await;
-
-        if let Err(err) = result {
-            handle_client_error(err, i, max_retries);
-            tokio::time::sleep(tokio::time::Duration::from_secs(2 * i as u64)).await;
-            continue;
+    endpoints: &[Endpoint],
+    union: bool,
+    offline: bool,
+    http: Option<&Http>,
+) -> Result<Retrieved, &'static str> {
+    let path = offline::path();
+    let mut stored = OfflineCodes::from_file(&path).unwrap_or_else(|e| {
+        err!("{}", e);
+        OfflineCodes::default()
+    });
+
+    if offline {
+        let retrieval = stored.lookup(endpoints, union).ok_or(
+            "No codes have been retrieved from these endpoints before, cannot run offline",
+        )?;
+
+        if (chrono::Utc::now() - retrieval.retrieved_at).num_hours() >= STALE_AFTER_HOURS {
+            err!(
+                "Using codes retrieved {} ago, they may be out of date",
+                retrieval.age()
+            );
+        } else {
+            println!("Using codes retrieved {} ago", retrieval.age());
+        }
+
+        return Ok(Retrieved {
+            codes: retrieval.codes,
+            rejected: vec![],
+        });
+    }
+
+    let result = retrieve(endpoints, union, http, &mut stored).await;
+
+    if let Err(e) = stored.write(&path) {
+        err!("{}", e);
+    }
+
+    match result {
+        Ok(retrieved) => Ok(retrieved),
+        Err(e) => {
+            let retrieval = stored.lookup(endpoints, union).ok_or(e)?;
+
+            err!(
+                "{}, falling back to codes retrieved {} ago, they may be out of date",
+                e,
+                retrieval.age()
+            );
+
+            Ok(Retrieved {
+                codes: retrieval.codes,
+                rejected: vec![],
+            })
+        }
+    }
+}
+
+/// Retrieve codes from the first endpoint that answers, falling back to the next one on failure.
+/// With `union`, every endpoint is asked and their codes are combined.
+async fn retrieve(
+    endpoints: &[Endpoint],
+    union: bool,
+    http: Option<&Http>,
+    stored: &mut OfflineCodes,
+) -> Result<Retrieved, &'static str> {
+    let mut retrieved = Retrieved::default();
+    let mut failure = None;
+
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        match get_codes_from(endpoint, http).await {
+            Ok(from_endpoint) => {
+                stored.store(endpoint, &from_endpoint.codes);
+                retrieved.codes.extend(from_endpoint.codes);
+                retrieved.rejected.extend(from_endpoint.rejected);
+
+                if !union {
+                    return Ok(retrieved);
+                }
+            }
+            Err(e) => {
+                if i + 1 < endpoints.len() {
+                    err!("{}, trying the next endpoint", e);
+                } else if !retrieved.codes.is_empty() {
+                    err!("{}, continuing with the codes retrieved so far", e);
+                }
+                failure = Some(e);
+            }
+        }
+    }
+
+    match failure {
+        Some(e) if retrieved.codes.is_empty() => Err(e),
+        _ => Ok(retrieved),
+    }
+}
+
+async fn get_codes_from(
+    endpoint: &Endpoint,
+    http: Option<&Http>,
+) -> Result<Retrieved, &'static str> {
+    match &endpoint.format {
+        Format::Licc => {
+            let base = endpoint.url.as_deref().unwrap_or(PUBLIC_INSTANCE_URL);
+            let feed = feed::Licc {
+                client: http_client(http.unwrap_or(&Http::default()), json_headers())?,
+                url: format!("{}/codes", base.trim_end_matches('/')),
+            };
+            get_entries(endpoint, &feed).await
+        }
+        Format::Json {
+            codes,
+            code,
+            expires_at,
+        } => {
+            let (client, url) = feed_client(endpoint, http)?;
+            let feed = feed::Json {
+                client,
+                url,
+                codes: codes.clone(),
+                code: code.clone(),
+                expires_at: expires_at.clone(),
+            };
+            get_entries(endpoint, &feed).await
+        }
+        Format::Text => {
+            let (client, url) = feed_client(endpoint, http)?;
+            get_entries(endpoint, &feed::Text { client, url }).await
+        }
+        #[cfg(feature = "feeds")]
+        Format::Rss => {
+            let (client, url) = feed_client(endpoint, http)?;
+            get_entries(endpoint, &feed::Rss { client, url }).await
+        }
+        #[cfg(not(feature = "feeds"))]
+        Format::Rss => Err("RSS and Atom feeds require the feeds feature"),
+    }
+}
+
+/// A plain HTTP client for endpoints that are not a codes API, these always need a url.
+fn feed_client(
+    endpoint: &Endpoint,
+    http: Option<&Http>,
+) -> Result<(reqwest::Client, String), &'static str> {
+    let url = endpoint
+        .url
+        .clone()
+        .ok_or("Endpoints that are not a codes API need a url")?;
+
+    Ok((
+        http_client(http.unwrap_or(&Http::default()), HeaderMap::new())?,
+        url,
+    ))
+}
+
+async fn get_entries(endpoint: &Endpoint, feed: &impl Feed) -> Result<Retrieved, &'static str> {
+    let policy = RetryPolicy::new(endpoint);
+    let started = Instant::now();
+
+    for attempt in 1..=policy.max_attempts {
+        let result = match endpoint.timeout_secs {
+            Some(secs) => tokio::time::timeout(Duration::from_secs(secs), feed.entries()).await,
+            None => Ok(feed.entries().await),
+        };
+
+        let (error, retryable) = match result {
+            Ok(Ok(entries)) => {
+                let mut retrieved = Retrieved::default();
+
+                for entry in entries {
+                    match parse(entry, endpoint) {
+                        Ok(code) => retrieved.codes.push(code),
+                        Err(rejected) => retrieved.rejected.push(rejected),
+                    }
+                }
+
+                return Ok(retrieved);
+            }
+            Ok(Err(FeedError::Client(err))) => {
+                let retryable = is_retryable(&err);
+                (
+                    handle_client_error(err, attempt, policy.max_attempts),
+                    retryable,
+                )
+            }
+            Ok(Err(FeedError::Parse(e))) => {
+                err!("Failed to parse codes: {}", e);
+                ("Failed to parse codes", false)
+            }
+            Err(_) => {
+                err!(
+                    "Timed out retrieving codes ({}/{})",
+                    attempt,
+                    policy.max_attempts
+                );
+                ("Timed out retrieving codes", true)
+            }
         };
 
-        return Ok(result.unwrap());
+        if !retryable {
+            return Err(error);
+        }
+
+        match policy.delay(attempt, started.elapsed()) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => break,
+        }
     }
 
     Err("Could not resolve codes within max retries")
 }
 
+fn parse(code: Entry, endpoint: &Endpoint) -> Result<RemoteCode, Rejected> {
+    let parsed = match code.malformed {
+        Some(reason) => Err(reason.to_string()),
+        None => Code::parse(&code.code),
+    };
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            err!("Skipping invalid code '{}': {}", code.code, e);
+            return Err(Rejected {
+                code: code.code,
+                endpoint: endpoint.url.clone(),
+            });
+        }
+    };
+
+    Ok(RemoteCode {
+        code: parsed,
+        expired: code.expired,
+        expires_at: code.expires_at.as_deref().and_then(parse_timestamp),
+        creator: code.creator,
+        creator_url: code.creator_url,
+        endpoint: endpoint.url.clone(),
+    })
+}
+
+/// The remote promises RFC3339, but has been known to send timestamps without a timezone (which are UTC).
+/// A plain date is taken as midnight UTC.
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(timestamp, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(timestamp, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .map(|naive| naive.and_utc())
+}
+
+/// Submission
+/// A code to contribute to a codes API, along with where it was found.
+#[derive(Debug, Clone)]
+pub struct Submission {
+    pub code: Code,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub creator: Option<String>,
+    pub creator_url: Option<String>,
+}
+
+/// Submit a code to the API at `remote.url`, returns the id the remote assigned to it if it told us.
+pub async fn submit(remote: &Remote, submission: Submission) -> Result<Option<i32>, &'static str> {
+    let api_key = remote
+        .api_key
+        .clone()
+        .ok_or("No API key configured, set remote.api_key in the config file")?;
+
+    let expires_at = submission
+        .expires_at
+        .unwrap_or_else(|| Utc::now() + chrono::Duration::days(DEFAULT_SUBMISSION_EXPIRY_DAYS));
+    if expires_at <= Utc::now() {
+        return Err("Cannot submit a code that has already expired");
+    }
+
+    let mut client = codes_client(Some(api_key), remote.url.clone(), remote.http.as_ref())?;
+
+    client
+        .insert_code(InsertCodeRequest {
+            code: submission.code.to_string(),
+            expires_at: expires_at.timestamp() as u64,
+            creator: SourceLookup {
+                name: submission
+                    .creator
+                    .unwrap_or_else(|| UNKNOWN_CREATOR.to_string()),
+                url: submission.creator_url.unwrap_or_default(),
+            },
+            submitter: None,
+        })
+        .await
+        .map_err(handle_submit_error)
+}
+
+/// A client for the codes API at `url` (the public instance if not set), configured with `http` if given.
+pub fn codes_client(
+    api_key: Option<String>,
+    url: Option<String>,
+    http: Option<&Http>,
+) -> Result<CodesClient, &'static str> {
+    let client = http
+        .map(|http| http_client(http, json_headers()))
+        .transpose()?;
+
+    Ok(CodesClient::new_full(
+        api_key.map(licc::api_key::ApiKey::new),
+        url,
+        client,
+    ))
+}
+
+/// The same headers licc uses for its own client.
+fn json_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    headers
+}
+
+pub(crate) fn http_client(
+    http: &Http,
+    headers: HeaderMap,
+) -> Result<reqwest::Client, &'static str> {
+    let user_agent = http.user_agent.clone().unwrap_or_else(|| {
+        format!(
+            "{}/{} ({})",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS
+        )
+    });
+
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .user_agent(user_agent);
+
+    if let Some(proxy) = &http.proxy {
+        let proxy = reqwest::Proxy::all(proxy).map_err(|e| {
+            err!("Invalid proxy '{}': {}", proxy, e);
+            "Invalid proxy url"
+        })?;
+        builder = builder.proxy(proxy);
+    }
+
+    for path in &http.root_certificates {
+        let pem = std::fs::read(path).map_err(|e| {
+            err!("Failed to read {}: {}", path.display(), e);
+            "Failed to read root certificate"
+        })?;
+        let certificate = reqwest::Certificate::from_pem(&pem).map_err(|e| {
+            err!("Invalid certificate in {}: {}", path.display(), e);
+            "Invalid root certificate"
+        })?;
+        builder = builder.add_root_certificate(certificate);
+    }
+
+    if let Some(secs) = http.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+
+    if let Some(secs) = http.request_timeout_secs {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+
+    builder.build().map_err(|e| {
+        err!("Failed to set up the HTTP client: {}", e);
+        "Failed to set up the HTTP client"
+    })
+}
+
+/// RetryPolicy
+/// How patiently we ask an endpoint for codes: the delay doubles after every failed attempt,
+/// with random jitter so clients don't retry in lockstep, until we run out of attempts or time.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u8,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub max_total: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(endpoint: &Endpoint) -> RetryPolicy {
+        RetryPolicy {
+            // avoid significant server flood
+            max_attempts: endpoint.max_retries.clamp(1, MAX_ATTEMPTS),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            max_total: Duration::from_secs(
+                endpoint.max_total_secs.unwrap_or(DEFAULT_MAX_TOTAL_SECS),
+            ),
+        }
+    }
+
+    /// How long to wait after the given (1-based) attempt failed, `None` if we should give up.
+    pub fn delay(&self, attempt: u8, elapsed: Duration) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let backoff = self
+            .initial_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        // Wait at least half the backoff, and a random part of the other half
+        let delay = backoff / 2 + backoff.mul_f64(fastrand::f64()) / 2;
+
+        if elapsed + delay > self.max_total {
+            return None;
+        }
+
+        Some(delay)
+    }
+}
+
+/// Whether trying again could help, a bad request or a response we cannot parse will not improve by itself.
+fn is_retryable(err: &ClientError) -> bool {
+    match err {
+        ClientError::Reqwest(err) => {
+            if err.is_decode() || err.is_builder() {
+                return false;
+            }
+
+            err.status()
+                .is_none_or(|status| status.is_server_error() || status.as_u16() == 429)
+        }
+        ClientError::Serde(_) | ClientError::ApiKeyMissing => false,
+        ClientError::ServerError(err) => err.error.code >= 500 || err.error.code == 429,
+    }
+}
+
 pub fn handle_client_error(err: ClientError, retry_count: u8, max_count: u8) -> &'static str {
     match err {
         ClientError::Reqwest(err) => {
@@ -56,5 +518,153 @@ pub fn handle_client_error(err: ClientError, retry_count: u8, max_count: u8) ->
             );
             "Failed to retrieve codes (server error)"
         }
+        ClientError::ApiKeyMissing => "An API key is required",
+    }
+}
+
+fn handle_submit_error(err: ClientError) -> &'static str {
+    match err {
+        ClientError::Reqwest(err) => {
+            err!("Failed to submit code: {}", err);
+            "Failed to submit code (http)"
+        }
+        ClientError::Serde(err) => {
+            err!("Failed to submit code: {}", err);
+            "Failed to submit code (serde)"
+        }
+        ClientError::ServerError(err) => {
+            err!(
+                "Failed to submit code (HTTP {}) {}",
+                err.error.code,
+                err.error.description
+            );
+            "Failed to submit code (server error)"
+        }
+        ClientError::ApiKeyMissing => "An API key is required to submit codes",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    const CODES: &str = r#"{
+        "codes": [{"code": "ABCD-EFGH-JKLM", "expired": false, "expires_at": "2030-01-01T00:00:00Z", "sources": {"creator": 1, "submitter": 1, "lister": 1}},
+                  {"code": "WXYZ-2345-6789", "expired": false, "expires_at": null, "sources": {"creator": 1, "submitter": 1, "lister": 1}}],
+        "sources": {"1": {"id": 1, "name": "Streamer", "url": "https://twitch.tv/streamer"}}
+    }"#;
+    const ERROR: &str = r#"{"error": {"code": 0, "description": "error"}}"#;
+
+    fn endpoint(server: &MockServer, max_retries: u8) -> Endpoint {
+        Endpoint {
+            url: Some(server.url.clone()),
+            max_retries,
+            ..Endpoint::default()
+        }
+    }
+
+    async fn fetch(endpoint: &Endpoint) -> Result<Retrieved, &'static str> {
+        get_codes_from(endpoint, None).await
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start(vec![(503, ERROR), (200, CODES)]);
+
+        let retrieved = fetch(&endpoint(&server, 3)).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            (requests[0].method.as_str(), requests[0].path.as_str()),
+            ("GET", "/codes")
+        );
+        assert!(requests[0]
+            .headers
+            .contains(&("accept".to_string(), "application/json".to_string())));
+        assert!(requests[0].body.is_empty());
+        assert_eq!(retrieved.codes[0].code.to_string(), "ABCD-EFGH-JKLM");
+        assert_eq!(retrieved.codes[0].creator.as_deref(), Some("Streamer"));
+        assert_eq!(
+            retrieved.codes[0].creator_url.as_deref(),
+            Some("https://twitch.tv/streamer")
+        );
+        // Codes by the same creator
+        assert_eq!(retrieved.codes[1].creator.as_deref(), Some("Streamer"));
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits() {
+        let server = MockServer::start(vec![(429, ERROR), (200, CODES)]);
+
+        assert!(fetch(&endpoint(&server, 3)).await.is_ok());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_client_errors() {
+        let server = MockServer::start(vec![(404, ERROR), (200, CODES)]);
+
+        assert!(fetch(&endpoint(&server, 3)).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_garbage() {
+        let server = MockServer::start(vec![(200, "<html>not codes</html>"), (200, CODES)]);
+
+        assert!(fetch(&endpoint(&server, 3)).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stops_retrying_after_max_total() {
+        let server = MockServer::start(vec![(503, ERROR)]);
+        let endpoint = Endpoint {
+            max_total_secs: Some(0),
+            ..endpoint(&server, 5)
+        };
+
+        assert!(fetch(&endpoint).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_json_entries_that_are_not_strings() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"items": [{"c": "ABCD-EFGH-JKLM"}, {"c": 123456789012}, {"c": null}, {"other": "x"}]}"#,
+        )]);
+        let endpoint = Endpoint {
+            format: Format::Json {
+                codes: "/items".to_string(),
+                code: Some("/c".to_string()),
+                expires_at: None,
+            },
+            ..endpoint(&server, 0)
+        };
+
+        let retrieved = fetch(&endpoint).await.unwrap();
+
+        assert_eq!(retrieved.codes.len(), 1);
+        let rejected: Vec<&str> = retrieved.rejected.iter().map(|r| r.code.as_str()).collect();
+        assert_eq!(rejected, vec!["123456789012", "null", r#"{"other":"x"}"#]);
+    }
+
+    #[test]
+    fn backs_off_exponentially_within_max_delay() {
+        let policy = RetryPolicy::new(&Endpoint {
+            max_retries: 5,
+            ..Endpoint::default()
+        });
+
+        for attempt in 1..5 {
+            let backoff = Duration::from_secs(1 << (attempt - 1));
+            let delay = policy.delay(attempt, Duration::ZERO).unwrap();
+
+            assert!(delay >= backoff / 2 && delay <= backoff);
+        }
+        assert!(policy.delay(5, Duration::ZERO).is_none());
     }
 }
@@ -0,0 +1,381 @@
+use crate::config::{self, Endpoint};
+use crate::err;
+use crate::source::{Source, SourcedCode};
+use chrono::Utc;
+use std::time::Duration;
+
+/// A code the API lists as active, although its expiry date has passed.
+#[allow(dead_code)] // Can be dead code if the feature is not enabled
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub code: String,
+    /// Creator the API listed the code with, kept as it is when reporting
+    pub creator: Option<String>,
+    pub creator_url: Option<String>,
+}
+
+/// Reporter
+/// Tells an API about codes it lists as active that have expired.
+pub trait Reporter {
+    fn report(&self, endpoint: &Endpoint, reports: &[Report]) -> Result<(), &'static str>;
+}
+
+/// Dispatcher
+/// Groups reports by the endpoint that listed the codes and sends them in batches, pausing between requests.
+pub struct Dispatcher {
+    reporter: Box<dyn Reporter>,
+    batch_size: usize,
+    delay: Duration,
+}
+
+impl Dispatcher {
+    #[allow(dead_code)] // Can be dead code if the feature is not enabled
+    pub fn new(reporter: Box<dyn Reporter>, reporting: &config::Reporting) -> Dispatcher {
+        Dispatcher {
+            reporter,
+            batch_size: reporting.batch_size.max(1),
+            delay: Duration::from_millis(reporting.delay_ms),
+        }
+    }
+
+    /// The dispatcher configured for the remote, `None` if reporting is not enabled.
+    #[cfg(feature = "remote")]
+    pub fn from_config(remote: &config::Remote) -> Option<Dispatcher> {
+        let reporting = remote.reporting.as_ref().filter(|r| r.enabled)?;
+
+        let Some(api_key) = remote.api_key.clone() else {
+            err!("Reporting codes requires remote.api_key in the config file, not reporting");
+            return None;
+        };
+
+        Some(Dispatcher::new(
+            Box::new(ApiReporter {
+                api_key,
+                http: remote.http.clone(),
+            }),
+            reporting,
+        ))
+    }
+
+    #[cfg(not(feature = "remote"))]
+    pub fn from_config(remote: &config::Remote) -> Option<Dispatcher> {
+        if remote.reporting.as_ref().is_some_and(|r| r.enabled) {
+            err!("Reporting codes is enabled, but the remote feature is not");
+        }
+
+        None
+    }
+
+    /// Report codes whose expiry date has passed back to the remote API that listed them as active.
+    /// Codes without an expiry date, codes the API already marks as expired and codes from the offline store
+    /// are left alone.
+    pub fn send(&self, codes: &[SourcedCode]) {
+        let now = Utc::now();
+        let reports = codes
+            .iter()
+            .filter(|code| !code.expired && code.expires_at.is_some_and(|at| at <= now))
+            .map(|code| {
+                let report = Report {
+                    code: code.code.to_string(),
+                    creator: code.creator.clone(),
+                    creator_url: code.creator_url.clone(),
+                };
+                (&code.origin, report)
+            });
+
+        let mut per_endpoint: Vec<(&Endpoint, Vec<Report>)> = vec![];
+        for (origin, report) in reports {
+            let Source::Remote {
+                endpoints,
+                offline: false,
+                ..
+            } = origin
+            else {
+                continue;
+            };
+            // Only codes APIs know what to do with a report
+            let Some(endpoint) = endpoints
+                .first()
+                .filter(|endpoint| endpoint.format == config::Format::Licc)
+            else {
+                continue;
+            };
+
+            match per_endpoint.iter_mut().find(|(e, _)| e.url == endpoint.url) {
+                Some((_, reports)) => reports.push(report),
+                None => per_endpoint.push((endpoint, vec![report])),
+            }
+        }
+
+        let mut first = true;
+        for (endpoint, reports) in per_endpoint {
+            let name = endpoint.url.as_deref().unwrap_or("the remote API");
+
+            for batch in reports.chunks(self.batch_size) {
+                if !first {
+                    std::thread::sleep(self.delay);
+                }
+                first = false;
+
+                match self.reporter.report(endpoint, batch) {
+                    Ok(_) => println!("Reported {} dead code(s) to {}", batch.len(), name),
+                    Err(e) => {
+                        err!("Failed to report codes to {}: {}", name, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reports codes to an `idle_champions_codes_api` instance, which requires an API key.
+/// The API has no route for reports, so codes are submitted again through `PUT /codes` with an expiry of now,
+/// after which the API lists them as expired.
+#[cfg(feature = "remote")]
+pub struct ApiReporter {
+    api_key: String,
+    http: Option<config::Http>,
+}
+
+#[cfg(feature = "remote")]
+impl Reporter for ApiReporter {
+    fn report(&self, endpoint: &Endpoint, reports: &[Report]) -> Result<(), &'static str> {
+        put(self, endpoint, reports)
+    }
+}
+
+#[cfg(feature = "remote")]
+#[tokio::main(flavor = "current_thread")]
+async fn put(
+    reporter: &ApiReporter,
+    endpoint: &Endpoint,
+    reports: &[Report],
+) -> Result<(), &'static str> {
+    let mut client = crate::remote::codes_client(
+        Some(reporter.api_key.clone()),
+        endpoint.url.clone(),
+        reporter.http.as_ref(),
+    )?;
+
+    let now = chrono::Utc::now().timestamp() as u64;
+
+    for report in reports {
+        client
+            .insert_code(licc::write::InsertCodeRequest {
+                code: report.code.clone(),
+                expires_at: now,
+                creator: licc::write::SourceLookup {
+                    name: report
+                        .creator
+                        .clone()
+                        .unwrap_or_else(|| crate::remote::UNKNOWN_CREATOR.to_string()),
+                    url: report.creator_url.clone().unwrap_or_default(),
+                },
+                submitter: None,
+            })
+            .await
+            .map_err(|e| {
+                err!("Failed to report {}: {:?}", report.code, e);
+                "Failed to report codes"
+            })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::Code;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    /// The endpoint a batch was reported to, its reports, and when it was sent.
+    type Batch = (String, Vec<Report>, Instant);
+
+    /// Records every batch it is asked to report.
+    #[derive(Default, Clone)]
+    struct Recorder {
+        batches: Arc<Mutex<Vec<Batch>>>,
+    }
+
+    impl Recorder {
+        /// The codes of every batch, per endpoint.
+        fn codes(&self) -> Vec<(String, Vec<String>)> {
+            self.batches
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(url, reports, _)| {
+                    let codes = reports.iter().map(|report| report.code.clone()).collect();
+                    (url.clone(), codes)
+                })
+                .collect()
+        }
+    }
+
+    impl Reporter for Recorder {
+        fn report(&self, endpoint: &Endpoint, reports: &[Report]) -> Result<(), &'static str> {
+            self.batches.lock().unwrap().push((
+                endpoint.url.clone().unwrap_or_default(),
+                reports.to_vec(),
+                Instant::now(),
+            ));
+            Ok(())
+        }
+    }
+
+    fn dispatcher(batch_size: usize, delay_ms: u64) -> (Dispatcher, Recorder) {
+        let recorder = Recorder::default();
+        let reporting = config::Reporting {
+            enabled: true,
+            batch_size,
+            delay_ms,
+        };
+
+        (
+            Dispatcher::new(Box::new(recorder.clone()), &reporting),
+            recorder,
+        )
+    }
+
+    fn remote(url: &str, format: config::Format, offline: bool) -> Source {
+        Source::Remote {
+            endpoints: vec![Endpoint {
+                url: Some(url.to_string()),
+                format,
+                ..Endpoint::default()
+            }],
+            union: false,
+            offline,
+            http: None,
+        }
+    }
+
+    /// Codes that expired yesterday.
+    fn expired(codes: &[&str], origin: &Source) -> Vec<SourcedCode> {
+        codes
+            .iter()
+            .map(|code| SourcedCode {
+                expires_at: Some(Utc::now() - chrono::Duration::days(1)),
+                ..SourcedCode::new(Code::parse(code).unwrap(), origin.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sends_batches_with_delays() {
+        let (dispatcher, recorder) = dispatcher(2, 50);
+        let origin = remote("http://a", config::Format::Licc, false);
+
+        dispatcher.send(&expired(
+            &[
+                "AAAA-AAAA-AAAA",
+                "BBBB-BBBB-BBBB",
+                "CCCC-CCCC-CCCC",
+                "DDDD-DDDD-DDDD",
+                "EEEE-EEEE-EEEE",
+            ],
+            &origin,
+        ));
+
+        let batches = recorder.batches.lock().unwrap();
+        let sizes: Vec<usize> = batches
+            .iter()
+            .map(|(_, reports, _)| reports.len())
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        for pair in batches.windows(2) {
+            assert!(pair[1].2 - pair[0].2 >= Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn groups_reports_per_endpoint() {
+        let (dispatcher, recorder) = dispatcher(10, 0);
+        let a = remote("http://a", config::Format::Licc, false);
+        let b = remote("http://b", config::Format::Licc, false);
+
+        let mut codes = expired(&["AAAA-AAAA-AAAA"], &a);
+        codes.extend(expired(&["BBBB-BBBB-BBBB"], &b));
+        codes.extend(expired(&["CCCC-CCCC-CCCC"], &a));
+        dispatcher.send(&codes);
+
+        assert_eq!(
+            recorder.codes(),
+            vec![
+                (
+                    "http://a".to_string(),
+                    vec!["AAAA-AAAA-AAAA".to_string(), "CCCC-CCCC-CCCC".to_string()]
+                ),
+                ("http://b".to_string(), vec!["BBBB-BBBB-BBBB".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_creator() {
+        let (dispatcher, recorder) = dispatcher(10, 0);
+        let mut codes = expired(
+            &["AAAA-AAAA-AAAA"],
+            &remote("http://a", config::Format::Licc, false),
+        );
+        codes[0].creator = Some("Streamer".to_string());
+        codes[0].creator_url = Some("https://twitch.tv/streamer".to_string());
+
+        dispatcher.send(&codes);
+
+        let batches = recorder.batches.lock().unwrap();
+        let report = &batches[0].1[0];
+        assert_eq!(report.creator.as_deref(), Some("Streamer"));
+        assert_eq!(
+            report.creator_url.as_deref(),
+            Some("https://twitch.tv/streamer")
+        );
+    }
+
+    #[test]
+    fn reports_only_codes_past_their_expiry() {
+        let (dispatcher, recorder) = dispatcher(10, 0);
+        let origin = remote("http://a", config::Format::Licc, false);
+        let mut codes = expired(&["AAAA-AAAA-AAAA", "BBBB-BBBB-BBBB"], &origin);
+        codes[1].expires_at = Some(Utc::now() + chrono::Duration::days(1));
+        // Whether a code works is not known without an expiry date
+        codes.push(SourcedCode::new(
+            Code::parse("CCCC-CCCC-CCCC").unwrap(),
+            origin.clone(),
+        ));
+
+        dispatcher.send(&codes);
+
+        assert_eq!(
+            recorder.codes(),
+            vec![("http://a".to_string(), vec!["AAAA-AAAA-AAAA".to_string()])]
+        );
+    }
+
+    #[test]
+    fn skips_codes_the_api_cannot_take() {
+        let (dispatcher, recorder) = dispatcher(10, 0);
+        let mut codes = expired(
+            &["AAAA-AAAA-AAAA"],
+            &remote("http://a", config::Format::Licc, true),
+        );
+        codes.extend(expired(
+            &["BBBB-BBBB-BBBB"],
+            &remote("http://b", config::Format::Text, false),
+        ));
+        codes.extend(expired(&["DDDD-DDDD-DDDD"], &Source::Checkpoint));
+        let mut known = expired(
+            &["CCCC-CCCC-CCCC"],
+            &remote("http://c", config::Format::Licc, false),
+        );
+        // The API already lists it as expired
+        known[0].expired = true;
+        codes.extend(known);
+
+        dispatcher.send(&codes);
+
+        assert!(recorder.batches.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::err;
+use chrono::{NaiveTime, Timelike};
+use directories::BaseDirs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const UNIT_NAME: &str = "icredeem";
+/// The service runs outside of the graphical session, these tell it how to reach the display
+const SESSION_VARIABLES: [&str; 3] = ["DISPLAY", "WAYLAND_DISPLAY", "XDG_RUNTIME_DIR"];
+
+/// Units
+/// The systemd user units that run `icredeem --no-interaction` once a day.
+pub struct Units {
+    pub service: String,
+    pub timer: String,
+}
+
+impl Units {
+    /// Generate the units for `executable`, which is run daily at `time` (local time).
+    /// `environment` is passed along, since the service runs outside of the graphical session.
+    pub fn new(executable: &Path, time: NaiveTime, environment: &[(&str, String)]) -> Units {
+        let mut service = format!(
+            "[Unit]\n\
+             Description=Redeem Idle Champions codes\n\
+             \n\
+             [Service]\n\
+             Type=oneshot\n\
+             ExecStart={} --no-interaction\n",
+            // Unlike Environment, ExecStart expands variables
+            quote(&executable.display().to_string()).replace('$', "$$")
+        );
+        for (name, value) in environment {
+            service.push_str(&format!(
+                "Environment={}\n",
+                quote(&format!("{}={}", name, value))
+            ));
+        }
+
+        let timer = format!(
+            "[Unit]\n\
+             Description=Redeem Idle Champions codes daily\n\
+             \n\
+             [Timer]\n\
+             OnCalendar=*-*-* {}\n\
+             Persistent=true\n\
+             \n\
+             [Install]\n\
+             WantedBy=timers.target\n",
+            time.format("%H:%M:00")
+        );
+
+        Units { service, timer }
+    }
+}
+
+/// The same schedule as a crontab line, for systems without systemd.
+pub fn cron_line(executable: &Path, time: NaiveTime, environment: &[(&str, String)]) -> String {
+    let env: String = environment
+        .iter()
+        .map(|(name, value)| format!("{}={} ", name, shell_quote(value)))
+        .collect();
+
+    format!(
+        "{} {} * * * {}{} --no-interaction",
+        time.minute(),
+        time.hour(),
+        env,
+        shell_quote(&executable.display().to_string())
+    )
+}
+
+/// The session variables that are set, to pass along to the scheduled run.
+fn environment() -> Vec<(&'static str, String)> {
+    SESSION_VARIABLES
+        .iter()
+        .filter_map(|name| Some((*name, std::env::var(name).ok()?)))
+        .collect()
+}
+
+/// Quote a value for a systemd unit file, `%` would otherwise start a specifier.
+fn quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+/// Quote a value for the shell that runs a crontab line, in which cron turns a bare `%` into a newline.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''").replace('%', "\\%"))
+}
+
+/// Where systemd looks for user units, `~/.config/systemd/user` on most systems.
+pub fn dir() -> PathBuf {
+    BaseDirs::new()
+        .unwrap()
+        .config_dir()
+        .join("systemd")
+        .join("user")
+}
+
+pub fn install(time: NaiveTime) -> Result<(), &'static str> {
+    let executable =
+        std::env::current_exe().map_err(|_| "Failed to determine the path of icredeem")?;
+    let environment = environment();
+    let units = Units::new(&executable, time, &environment);
+
+    let dir = dir();
+    std::fs::create_dir_all(&dir).map_err(|_| "Failed to create the systemd user directory")?;
+    std::fs::write(dir.join(format!("{}.service", UNIT_NAME)), units.service)
+        .map_err(|_| "Failed to write the service unit")?;
+    std::fs::write(dir.join(format!("{}.timer", UNIT_NAME)), units.timer)
+        .map_err(|_| "Failed to write the timer unit")?;
+
+    println!(
+        "Installed {}.service and {}.timer in {}",
+        UNIT_NAME,
+        UNIT_NAME,
+        dir.display()
+    );
+
+    let enabled = systemctl(&["daemon-reload"])
+        .and_then(|_| systemctl(&["enable", "--now", &format!("{}.timer", UNIT_NAME)]));
+
+    if enabled.is_ok() {
+        println!("icredeem now runs daily at {}.", time.format("%H:%M"));
+    }
+    // Printed regardless, it is the way to go when systemd is not available
+    println!("Without systemd, add this line to your crontab (crontab -e) instead:");
+    println!("  {}", cron_line(&executable, time, &environment));
+
+    enabled
+}
+
+pub fn status() -> Result<(), &'static str> {
+    let timer = dir().join(format!("{}.timer", UNIT_NAME));
+    if !timer.exists() {
+        println!("No schedule installed, see `icredeem schedule install --help`.");
+        return Ok(());
+    }
+
+    let output = Command::new("systemctl")
+        .args(["--user", "list-timers", &format!("{}.timer", UNIT_NAME)])
+        .output()
+        .map_err(|e| {
+            err!("Failed to run systemctl: {}", e);
+            "Failed to query systemd"
+        })?;
+
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+
+    Ok(())
+}
+
+pub fn remove() -> Result<(), &'static str> {
+    let dir = dir();
+    let timer = dir.join(format!("{}.timer", UNIT_NAME));
+    let service = dir.join(format!("{}.service", UNIT_NAME));
+
+    if !timer.exists() && !service.exists() {
+        println!("No schedule installed.");
+        return Ok(());
+    }
+
+    if let Err(e) = systemctl(&["disable", "--now", &format!("{}.timer", UNIT_NAME)]) {
+        err!("{}, removing the units anyway", e);
+    }
+
+    for path in [timer, service] {
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|_| "Failed to remove a systemd unit")?;
+        }
+    }
+
+    systemctl(&["daemon-reload"])?;
+
+    println!("Schedule removed.");
+
+    Ok(())
+}
+
+fn systemctl(args: &[&str]) -> Result<(), &'static str> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .map_err(|e| {
+            err!("Failed to run systemctl: {}", e);
+            "Failed to run systemctl"
+        })?;
+
+    if !status.success() {
+        return Err("systemctl returned an error");
+    }
+
+    Ok(())
+}
+
+/// Parse a time of day such as "09:00".
+pub fn parse_time(input: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(input, "%H:%M")
+        .map_err(|_| format!("Invalid time '{}', use HH:MM, e.g. 09:00", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment() -> Vec<(&'static str, String)> {
+        vec![
+            ("DISPLAY", ":0".to_string()),
+            ("WAYLAND_DISPLAY", "wayland-1".to_string()),
+            ("XDG_RUNTIME_DIR", "/run/user/1000".to_string()),
+        ]
+    }
+
+    fn time() -> NaiveTime {
+        NaiveTime::from_hms_opt(9, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn units_match_golden_files() {
+        let units = Units::new(
+            Path::new("/opt/Idle Tools/icredeem"),
+            time(),
+            &environment(),
+        );
+
+        assert_eq!(
+            units.service,
+            include_str!("../tests/golden/icredeem.service")
+        );
+        assert_eq!(units.timer, include_str!("../tests/golden/icredeem.timer"));
+    }
+
+    #[test]
+    fn cron_line_passes_the_session_along() {
+        assert_eq!(
+            cron_line(Path::new("/opt/Idle Tools/icredeem"), time(), &environment()),
+            "30 9 * * * DISPLAY=':0' WAYLAND_DISPLAY='wayland-1' XDG_RUNTIME_DIR='/run/user/1000' '/opt/Idle Tools/icredeem' --no-interaction"
+        );
+    }
+
+    #[test]
+    fn escapes_what_systemd_and_cron_would_interpret() {
+        assert_eq!(quote(r#"/it's "100%"\"#), r#""/it's \"100%%\"\\""#);
+        assert_eq!(shell_quote("/it's 100%"), r#"'/it'\''s 100\%'"#);
+
+        let units = Units::new(Path::new("/home/$USER/icredeem"), time(), &[]);
+        assert!(units
+            .service
+            .contains("ExecStart=\"/home/$$USER/icredeem\" --no-interaction\n"));
+    }
+}
@@ -0,0 +1,563 @@
+#![cfg(feature = "serve")]
+
+use crate::app::{interactor, Settings};
+use crate::cache::{self, Cache};
+use crate::code::Code;
+use crate::interaction::join;
+use crate::webhook::Webhooks;
+use crate::{abort, err};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const ABORT_REASON: &str = "Aborted through the control API";
+const TOKEN_FILE_NAME: &str = "serve_token";
+const EXTENSION_SCHEMES: [&str; 3] = [
+    "chrome-extension://",
+    "moz-extension://",
+    "safari-web-extension://",
+];
+
+/// State
+/// What the control API and the redeeming loop share.
+#[derive(Debug, Default)]
+struct State {
+    queue: Vec<Code>,
+    /// The codes being redeemed right now
+    redeeming: Vec<Code>,
+    paused: bool,
+    history: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Entry {
+    code: Code,
+    outcome: Outcome,
+    at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Redeemed,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Deserialize)]
+struct Enqueue {
+    codes: Vec<String>,
+}
+
+/// Access
+/// Who may use the control API: local tools and allowed browser extensions that know the token.
+/// Web pages can reach localhost too, so requests from other origins or through DNS rebinding
+/// (with another `Host`) are refused.
+struct Access {
+    token: String,
+    port: u16,
+    origins: Vec<String>,
+}
+
+impl Access {
+    /// Only browser extensions can be allowed, a web page's origin is shared by everything it loads.
+    fn new(token: String, port: u16, origins: Vec<String>) -> Self {
+        let (origins, ignored): (Vec<String>, Vec<String>) =
+            origins.into_iter().partition(|origin| {
+                EXTENSION_SCHEMES
+                    .iter()
+                    .any(|scheme| origin.len() > scheme.len() && origin.starts_with(scheme))
+            });
+        for origin in ignored {
+            err!(
+                "Ignoring allowed origin '{}', only browser extensions can be allowed",
+                origin
+            );
+        }
+
+        Access {
+            token,
+            port,
+            origins,
+        }
+    }
+
+    /// The request's `Origin`, if it is allowed.
+    fn allowed_origin<'a>(&self, headers: &'a [Header]) -> Option<&'a str> {
+        header(headers, "Origin").filter(|origin| self.origins.iter().any(|o| o == origin))
+    }
+
+    /// Preflight requests from allowed origins pass without the token, browsers never send it with them.
+    fn check(&self, method: &Method, headers: &[Header]) -> Result<(), (u16, Value)> {
+        let header = |name: &str| header(headers, name);
+
+        let origin = self.allowed_origin(headers);
+        if header("Origin").is_some() && origin.is_none() {
+            return Err((
+                403,
+                json!({ "error": "Requests from this origin are not allowed" }),
+            ));
+        }
+
+        let hosts = [
+            format!("127.0.0.1:{}", self.port),
+            format!("localhost:{}", self.port),
+        ];
+        if !header("Host").is_some_and(|host| hosts.iter().any(|h| h == host)) {
+            return Err((403, json!({ "error": "Unexpected Host" })));
+        }
+
+        if *method == Method::Options && origin.is_some() {
+            return Ok(());
+        }
+
+        let authorized = header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()));
+        if !authorized {
+            return Err((401, json!({ "error": "Missing or invalid bearer token" })));
+        }
+
+        let json = header("Content-Type").is_some_and(|value| {
+            value
+                .split(';')
+                .next()
+                .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+        });
+        if *method == Method::Post && !json {
+            return Err((
+                415,
+                json!({ "error": "Expected Content-Type: application/json" }),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn header<'a>(headers: &'a [Header], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Headers that let an allowed extension read the response, and send its requests after a preflight.
+fn cors_headers(origin: &str, preflight: bool) -> Vec<Header> {
+    let mut headers = vec![("Access-Control-Allow-Origin", origin), ("Vary", "Origin")];
+    if preflight {
+        headers.extend([
+            ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
+            (
+                "Access-Control-Allow-Headers",
+                "Authorization, Content-Type",
+            ),
+            ("Access-Control-Max-Age", "600"),
+        ]);
+    }
+
+    headers
+        .into_iter()
+        .map(|(name, value)| Header::from_bytes(name, value).unwrap())
+        .collect()
+}
+
+/// Compare without bailing out at the first difference, so the token cannot be guessed byte by byte from timings.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub fn token_path() -> PathBuf {
+    crate::config::dir().join(TOKEN_FILE_NAME)
+}
+
+/// The token requests have to carry, generated on first use.
+fn token(path: &PathBuf) -> Result<String, &'static str> {
+    if path.exists() {
+        let token = std::fs::read_to_string(path).map_err(|_| "Failed to read the token file")?;
+        return Ok(token.trim().to_string());
+    }
+
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|_| "Failed to generate a token")?;
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(|_| "Failed to create config directory")?;
+    write_private(path, &token).map_err(|_| "Failed to write the token file")?;
+
+    Ok(token)
+}
+
+#[cfg(unix)]
+fn write_private(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+/// Listen on localhost for codes to redeem, redeeming them as they come in until interrupted.
+/// Requests need `Authorization: Bearer <token>`, with the token from [`token_path`].
+/// Browser extensions in `origins` may use the API too.
+///
+/// - `POST /codes` with `{"codes": [...]}` queues codes
+/// - `GET /queue`, `GET /status` and `GET /history` tell what is going on
+/// - `POST /pause`, `POST /resume` and `POST /abort` control the redemption
+pub fn serve(
+    settings: Settings,
+    webhooks: Option<Webhooks>,
+    port: u16,
+    origins: Vec<String>,
+) -> Result<(), &'static str> {
+    let path = token_path();
+    let access = Access::new(token(&path)?, port, origins);
+
+    let server = Server::http(("127.0.0.1", port)).map_err(|e| {
+        err!("Failed to listen on port {}: {}", port, e);
+        "Failed to start the control API"
+    })?;
+    let state = Arc::new(Mutex::new(State::default()));
+
+    let shared = Arc::clone(&state);
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(&shared, &access, request);
+        }
+    });
+
+    let mut interactor = interactor(settings)?;
+
+    println!(
+        "Listening on http://127.0.0.1:{}, keep the game on the Chest menu. Press Ctrl-C to stop.",
+        port
+    );
+    println!(
+        "Requests need 'Authorization: Bearer <token>', the token is in {}",
+        path.display()
+    );
+
+    loop {
+        let batch = {
+            let mut state = state.lock().unwrap();
+            if state.paused || state.queue.is_empty() {
+                None
+            } else {
+                state.redeeming = std::mem::take(&mut state.queue);
+                Some(state.redeeming.clone())
+            }
+        };
+
+        let Some(batch) = batch else {
+            std::thread::sleep(Duration::from_millis(500));
+            continue;
+        };
+
+        let result = interactor.redeem_many(batch);
+
+        let summary = interactor.take_summary();
+        if !summary.attempts.is_empty() {
+            println!("{}", summary);
+        }
+
+        if let Some(webhooks) = webhooks.as_ref().filter(|_| summary.has_codes()) {
+            webhooks.send(&summary);
+        }
+
+        let mut state = state.lock().unwrap();
+        let now = Utc::now();
+        let outcomes = [
+            (&summary.redeemed, Outcome::Redeemed),
+            (&summary.skipped, Outcome::Skipped),
+        ];
+        for (codes, outcome) in outcomes {
+            state.history.extend(codes.iter().map(|code| Entry {
+                code: code.clone(),
+                outcome,
+                at: now,
+            }));
+        }
+        state.redeeming.clear();
+
+        let Err(failed_codes) = result else {
+            continue;
+        };
+
+        let (mut failed, mut untried): (Vec<Code>, Vec<Code>) = failed_codes
+            .into_iter()
+            .partition(|code| summary.attempts_for(code).next().is_some());
+
+        if abort::is_tripped() {
+            if abort::is_interrupted() {
+                return Err(abort::INTERRUPTED);
+            }
+
+            // Codes that were never tried go back in the queue, until the user resumes
+            println!("{}, pausing. POST /resume to continue.", abort::reason());
+            untried.append(&mut state.queue);
+            state.queue = untried;
+            state.paused = true;
+        } else {
+            failed.append(&mut untried);
+        }
+
+        if !failed.is_empty() {
+            err!("Failed to redeem codes: {}", join(&failed));
+            state.history.extend(failed.into_iter().map(|code| Entry {
+                code,
+                outcome: Outcome::Failed,
+                at: now,
+            }));
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, access: &Access, mut request: Request) {
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let preflight = *request.method() == Method::Options;
+    let origin = access.allowed_origin(request.headers()).map(str::to_string);
+
+    let (status, body) = match access.check(request.method(), request.headers()) {
+        Ok(_) if preflight => (204, Value::Null),
+        Ok(_) => route(state, &mut request, &path),
+        Err(rejection) => rejection,
+    };
+
+    let body = if status == 204 {
+        String::new()
+    } else {
+        body.to_string()
+    };
+    let mut response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    for header in origin
+        .map(|origin| cors_headers(&origin, preflight))
+        .unwrap_or_default()
+    {
+        response.add_header(header);
+    }
+
+    if let Err(e) = request.respond(response) {
+        err!("Failed to respond to a control API request: {}", e);
+    }
+}
+
+fn route(state: &Mutex<State>, request: &mut Request, path: &str) -> (u16, Value) {
+    match (request.method(), path) {
+        (Method::Post, "/codes") => {
+            let mut body = String::new();
+            match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => enqueue(state, &body),
+                Err(_) => (400, json!({ "error": "Failed to read the request body" })),
+            }
+        }
+        (Method::Get, "/queue") => (200, json!({ "queue": state.lock().unwrap().queue })),
+        (Method::Get, "/status") => {
+            let state = state.lock().unwrap();
+            (
+                200,
+                json!({
+                    "paused": state.paused,
+                    "redeeming": state.redeeming,
+                    "queued": state.queue.len(),
+                }),
+            )
+        }
+        (Method::Get, "/history") => (200, json!({ "history": state.lock().unwrap().history })),
+        (Method::Post, "/pause") => {
+            state.lock().unwrap().paused = true;
+            (200, json!({ "paused": true }))
+        }
+        (Method::Post, "/resume") => {
+            state.lock().unwrap().paused = false;
+            (200, json!({ "paused": false }))
+        }
+        (Method::Post, "/abort") => {
+            if state.lock().unwrap().redeeming.is_empty() {
+                (409, json!({ "error": "Not redeeming" }))
+            } else {
+                abort::trip(ABORT_REASON);
+                (200, json!({ "aborted": true }))
+            }
+        }
+        _ => (404, json!({ "error": "Not found" })),
+    }
+}
+
+/// Queue the codes in the request, codes that were already redeemed, queued or are being redeemed are skipped.
+/// Nothing is queued if any of the codes is invalid.
+fn enqueue(state: &Mutex<State>, body: &str) -> (u16, Value) {
+    let Ok(request) = serde_json::from_str::<Enqueue>(body) else {
+        return (400, json!({ "error": "Expected {\"codes\": [...]}" }));
+    };
+
+    let mut codes = vec![];
+    let mut invalid = vec![];
+    for input in &request.codes {
+        match Code::parse(input) {
+            Ok(code) => codes.push(code),
+            Err(e) => invalid.push(json!({ "code": input, "error": e })),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return (400, json!({ "error": "Invalid codes", "invalid": invalid }));
+    }
+
+    let cache = Cache::from_file(&cache::path()).unwrap_or_else(|e| {
+        err!("Failed to read cache from file: {}", e);
+        Cache::new()
+    });
+
+    let mut state = state.lock().unwrap();
+    let (mut queued, mut skipped) = (vec![], vec![]);
+    for code in codes {
+        if cache.contains(&code) || state.queue.contains(&code) || state.redeeming.contains(&code) {
+            skipped.push(code);
+        } else {
+            state.queue.push(code.clone());
+            queued.push(code);
+        }
+    }
+
+    if !queued.is_empty() {
+        println!("Queued {} code(s): {}", queued.len(), join(&queued));
+    }
+
+    (200, json!({ "queued": queued, "skipped": skipped }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+    const EXTENSION: &str = "chrome-extension://abcdefghijklmnopabcdefghijklmnop";
+
+    fn check(method: Method, headers: &[(&str, &str)]) -> u16 {
+        let access = Access::new(
+            TOKEN.to_string(),
+            7878,
+            vec![EXTENSION.to_string(), "https://example.com".to_string()],
+        );
+        let headers: Vec<Header> = headers
+            .iter()
+            .map(|(name, value)| Header::from_bytes(*name, *value).unwrap())
+            .collect();
+
+        match access.check(&method, &headers) {
+            Ok(_) => 200,
+            Err((status, _)) => status,
+        }
+    }
+
+    fn valid() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("Host", "127.0.0.1:7878"),
+            ("Authorization", "Bearer 0123456789abcdef"),
+            ("Content-Type", "application/json; charset=utf-8"),
+        ]
+    }
+
+    #[test]
+    fn accepts_local_tools_with_the_token() {
+        assert_eq!(check(Method::Post, &valid()), 200);
+        assert_eq!(check(Method::Get, &valid()[..2]), 200);
+
+        let mut headers = valid();
+        headers[0] = ("Host", "localhost:7878");
+        assert_eq!(check(Method::Post, &headers), 200);
+    }
+
+    #[test]
+    fn requires_the_token() {
+        let mut headers = valid();
+        headers[1] = ("Authorization", "Bearer 0123456789abcdeF");
+        assert_eq!(check(Method::Get, &headers), 401);
+
+        headers[1] = ("Authorization", TOKEN);
+        assert_eq!(check(Method::Get, &headers), 401);
+
+        headers.remove(1);
+        assert_eq!(check(Method::Get, &headers), 401);
+    }
+
+    #[test]
+    fn accepts_allowed_extensions_with_the_token() {
+        let mut headers = valid();
+        headers.push(("Origin", EXTENSION));
+        assert_eq!(check(Method::Post, &headers), 200);
+
+        headers.remove(1);
+        assert_eq!(check(Method::Post, &headers), 401);
+    }
+
+    #[test]
+    fn answers_preflight_for_allowed_extensions() {
+        let headers = [("Host", "127.0.0.1:7878"), ("Origin", EXTENSION)];
+        assert_eq!(check(Method::Options, &headers), 200);
+
+        let headers = [
+            ("Host", "127.0.0.1:7878"),
+            ("Origin", "moz-extension://1234"),
+        ];
+        assert_eq!(check(Method::Options, &headers), 403);
+
+        assert_eq!(check(Method::Options, &headers[..1]), 401);
+    }
+
+    #[test]
+    fn refuses_other_origins() {
+        // Web pages cannot be allowed, even when configured
+        for origin in [
+            "https://example.com",
+            "null",
+            "chrome-extension://ponmlkjihgfedcbaponmlkjihgfedcba",
+            "chrome-extension://abcdefghijklmnopabcdefghijklmnop.example.com",
+        ] {
+            let mut headers = valid();
+            headers.push(("Origin", origin));
+            assert_eq!(check(Method::Post, &headers), 403, "{}", origin);
+        }
+    }
+
+    #[test]
+    fn refuses_other_hosts() {
+        for host in ["example.com:7878", "127.0.0.1:8080", "127.0.0.1"] {
+            let mut headers = valid();
+            headers[0] = ("Host", host);
+            assert_eq!(check(Method::Post, &headers), 403, "{}", host);
+        }
+    }
+
+    #[test]
+    fn requires_json_posts() {
+        let mut headers = valid();
+        headers[2] = ("Content-Type", "text/plain");
+        assert_eq!(check(Method::Post, &headers), 415);
+
+        headers.remove(2);
+        assert_eq!(check(Method::Post, &headers), 415);
+    }
+}
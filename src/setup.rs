@@ -1,3 +1,4 @@
+use crate::code::Code;
 use crate::config::Strategy::Remote;
 use crate::config::{dir, file, write, ConfigFile, Instructions};
 use crate::err;
@@ -52,6 +53,14 @@ pub fn setup() -> Result<(), &'static str> {
         default_strategy: Remote,
         instructions,
         remote: None,
+        abort: None,
+        retry: None,
+        focus: None,
+        input: None,
+        clipboard: None,
+        notifications: None,
+        webhooks: vec![],
+        serve: None,
         slow: false,
     })?;
 
@@ -62,7 +71,7 @@ fn demo(instructions: &Instructions) -> Result<(), String> {
     println!("We will now test a full cycle of the program.");
     println!("This will open the chest UI, unlock a chest, and close the UI. Please avoid using the mouse and keyboard.");
     let mut interactor = Interactor::new(*instructions, false, true).map_err(|e| e.to_string())?;
-    interactor.redeem("DEMO-REDE-EMER-IDLE")
+    interactor.redeem(&Code::parse("DEMO-REDE-EMER-IDLE")?)
 }
 
 fn get_cursor_position(enigo: &enigo::Enigo) -> Result<Coordinates, &'static str> {
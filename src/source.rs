@@ -0,0 +1,271 @@
+use crate::checkpoint::{self, Checkpoint};
+use crate::code::Code;
+use crate::config::{Endpoint, Http};
+use crate::err;
+use chrono::{DateTime, Utc};
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Source
+/// Somewhere codes come from, a single run may combine any number of them.
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// Codes given on the command line
+    Cli(Vec<Code>),
+    /// A file with one code per line
+    File(PathBuf),
+    /// One or more `idle_champions_codes_api` instances, tried in order
+    Remote {
+        endpoints: Vec<Endpoint>,
+        union: bool,
+        /// Only use the codes the endpoints returned last time
+        offline: bool,
+        http: Option<Http>,
+    },
+    /// Codes left over from an interrupted run
+    Checkpoint,
+}
+
+/// SourcedCode
+/// A code tagged with the source it came from, and what that source knows about it.
+#[derive(Debug, Clone)]
+pub struct SourcedCode {
+    pub code: Code,
+    pub origin: Source,
+    pub expired: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub creator: Option<String>,
+    pub creator_url: Option<String>,
+}
+
+/// Something a source listed that is not a valid code.
+#[derive(Debug, Clone)]
+pub struct InvalidCode {
+    pub code: String,
+    pub origin: Source,
+}
+
+/// Collected
+/// The codes the sources gave us, and what they listed that is not a valid code.
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub codes: Vec<SourcedCode>,
+    pub invalid: Vec<InvalidCode>,
+}
+
+/// Fetch codes from every source, deduplicated.
+/// The first source to mention a code is its origin, later sources may fill in what it did not know.
+/// A failing source is skipped, the run only fails if no source produced any codes.
+pub fn collect(sources: &[Source]) -> Result<Collected, &'static str> {
+    let mut collected = Collected::default();
+    let mut failure = None;
+
+    for source in sources {
+        let fetched = match source.fetch() {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                err!("Failed to retrieve codes from {}: {}", source, e);
+                failure = Some(e);
+                continue;
+            }
+        };
+
+        for code in fetched.codes {
+            match collected.codes.iter_mut().find(|c| c.code == code.code) {
+                Some(existing) => existing.merge(code),
+                None => collected.codes.push(code),
+            }
+        }
+
+        collected.invalid.extend(fetched.invalid);
+    }
+
+    match failure {
+        Some(e) if collected.codes.is_empty() => Err(e),
+        _ => Ok(collected),
+    }
+}
+
+impl Source {
+    pub fn fetch(&self) -> Result<Collected, &'static str> {
+        match self {
+            Source::Cli(codes) => Ok(self.tag(codes.clone())),
+            Source::File(path) => self.read_file(path),
+            Source::Remote {
+                endpoints,
+                union,
+                offline,
+                http,
+            } => fetch_remote(endpoints, *union, *offline, http.as_ref()),
+            Source::Checkpoint => {
+                let checkpoint = Checkpoint::from_file(&checkpoint::path())?;
+
+                println!(
+                    "Resuming interrupted run: {} completed, {} failed, {} pending",
+                    checkpoint.completed.len(),
+                    checkpoint.failed.len(),
+                    checkpoint.pending.len()
+                );
+
+                Ok(self.tag(checkpoint.remaining()))
+            }
+        }
+    }
+
+    fn tag(&self, codes: Vec<Code>) -> Collected {
+        Collected {
+            codes: codes
+                .into_iter()
+                .map(|code| SourcedCode::new(code, self.clone()))
+                .collect(),
+            invalid: vec![],
+        }
+    }
+
+    fn read_file(&self, path: &PathBuf) -> Result<Collected, &'static str> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            err!("Failed to read {}: {}", path.display(), e);
+            "Failed to read code file"
+        })?;
+
+        let mut collected = Collected::default();
+
+        // Blank lines and lines starting with '#' are ignored
+        for line in contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            match Code::parse(line) {
+                Ok(code) => collected.codes.push(SourcedCode::new(code, self.clone())),
+                Err(e) => {
+                    err!("Skipping invalid code '{}': {}", line, e);
+                    collected.invalid.push(InvalidCode {
+                        code: line.to_string(),
+                        origin: self.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(collected)
+    }
+}
+
+impl SourcedCode {
+    pub fn new(code: Code, origin: Source) -> SourcedCode {
+        SourcedCode {
+            code,
+            origin,
+            expired: false,
+            expires_at: None,
+            creator: None,
+            creator_url: None,
+        }
+    }
+
+    /// Whether the code has expired, trusting the timestamp over the source's own judgement when we have one.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= now,
+            None => self.expired,
+        }
+    }
+
+    fn merge(&mut self, other: SourcedCode) {
+        self.expired |= other.expired;
+        self.expires_at = self.expires_at.or(other.expires_at);
+        if self.creator.is_none() {
+            self.creator = other.creator;
+            self.creator_url = other.creator_url;
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+#[tokio::main(flavor = "current_thread")]
+async fn fetch_remote(
+    endpoints: &[Endpoint],
+    union: bool,
+    offline: bool,
+    http: Option<&Http>,
+) -> Result<Collected, &'static str> {
+    let retrieved = crate::remote::get_codes(endpoints, union, offline, http).await?;
+
+    // Narrow the origin down to the endpoint that answered
+    let origin = |url: &Option<String>| Source::Remote {
+        endpoints: endpoints
+            .iter()
+            .filter(|endpoint| &endpoint.url == url)
+            .take(1)
+            .cloned()
+            .collect(),
+        union: false,
+        offline,
+        http: http.cloned(),
+    };
+
+    Ok(Collected {
+        codes: retrieved
+            .codes
+            .into_iter()
+            .map(|remote| SourcedCode {
+                code: remote.code,
+                origin: origin(&remote.endpoint),
+                expired: remote.expired,
+                expires_at: remote.expires_at,
+                creator: remote.creator,
+                creator_url: remote.creator_url,
+            })
+            .collect(),
+        invalid: retrieved
+            .rejected
+            .into_iter()
+            .map(|rejected| InvalidCode {
+                origin: origin(&rejected.endpoint),
+                code: rejected.code,
+            })
+            .collect(),
+    })
+}
+
+#[cfg(not(feature = "remote"))]
+fn fetch_remote(
+    _endpoints: &[Endpoint],
+    _union: bool,
+    _offline: bool,
+    _http: Option<&Http>,
+) -> Result<Collected, &'static str> {
+    Err("Remote feature not enabled")
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Cli(_) => write!(f, "the command line"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Remote { endpoints, .. } => match endpoints.as_slice() {
+                [Endpoint { url: Some(url), .. }] => write!(f, "{}", url),
+                [_] => write!(f, "the remote API"),
+                _ => write!(f, "{} remote endpoints", endpoints.len()),
+            },
+            Source::Checkpoint => write!(f, "the interrupted run"),
+        }
+    }
+}
+
+impl Display for SourcedCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+
+        if let Some(expires_at) = self.expires_at {
+            write!(f, " (expires {})", expires_at.format("%Y-%m-%d %H:%M UTC"))?;
+        }
+
+        if let Some(creator) = &self.creator {
+            write!(f, " by {}", creator)?;
+        }
+
+        write!(f, ", from {}", self.origin)
+    }
+}
@@ -0,0 +1,93 @@
+use crate::code::Code;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Summary
+/// What happened to every code during a run, including each individual attempt.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Summary {
+    pub redeemed: Vec<Code>,
+    pub skipped: Vec<Code>,
+    pub failed: Vec<Code>,
+    pub attempts: Vec<Attempt>,
+    /// When codes expire, if known
+    pub expiry: HashMap<Code, DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Attempt {
+    pub code: Code,
+    pub number: u8,
+    pub error: Option<String>,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary::default()
+    }
+
+    pub fn record(&mut self, code: &Code, error: Option<String>) {
+        let number = self.attempts_for(code).count() as u8 + 1;
+
+        self.attempts.push(Attempt {
+            code: code.clone(),
+            number,
+            error,
+        });
+    }
+
+    pub fn with_expiry(mut self, expiry: HashMap<Code, DateTime<Utc>>) -> Summary {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Whether any code was redeemed, skipped or failed, i.e. there is something to report.
+    pub fn has_codes(&self) -> bool {
+        !self.redeemed.is_empty() || !self.skipped.is_empty() || !self.failed.is_empty()
+    }
+
+    pub fn attempts_for<'a>(&'a self, code: &'a Code) -> impl Iterator<Item = &'a Attempt> + 'a {
+        self.attempts.iter().filter(move |a| &a.code == code)
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Redeemed {}, skipped {} (already redeemed), failed {}",
+            self.redeemed.len(),
+            self.skipped.len(),
+            self.failed.len()
+        )?;
+
+        let mut codes: Vec<&Code> = vec![];
+        for attempt in &self.attempts {
+            if !codes.contains(&&attempt.code) {
+                codes.push(&attempt.code);
+            }
+        }
+
+        for code in codes {
+            let outcome = if self.redeemed.contains(code) {
+                "redeemed"
+            } else {
+                "failed"
+            };
+            write!(f, "\n  {}: {}", code, outcome)?;
+
+            if let Some(expires_at) = self.expiry.get(code) {
+                write!(f, " (expires {})", expires_at.format("%Y-%m-%d %H:%M UTC"))?;
+            }
+
+            for attempt in self.attempts_for(code) {
+                if let Some(error) = &attempt.error {
+                    write!(f, "\n    attempt {}: {}", attempt.number, error)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
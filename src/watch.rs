@@ -0,0 +1,253 @@
+#![cfg(feature = "remote")]
+
+use crate::app::{interactor, RunInstructions};
+use crate::cache::{self, Cache};
+use crate::code::Code;
+use crate::focus::FocusGuard;
+use crate::interaction::{await_enter, join};
+use crate::notify::{self, Event};
+use crate::source::collect;
+use crate::{abort, err};
+use chrono::{DateTime, Local, Utc};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Poll the sources every `interval` and redeem the codes that are not in the cache yet, until interrupted.
+/// New codes wait for the next poll while the game is not focused, or for ENTER with `confirm`.
+/// When focus cannot be detected, we ask for ENTER as well rather than typing into whatever window is in front.
+pub fn watch(
+    instructions: RunInstructions,
+    interval: Duration,
+    confirm: bool,
+) -> Result<(), &'static str> {
+    let RunInstructions {
+        sources,
+        settings,
+        webhooks,
+        ..
+    } = instructions;
+
+    let focus = FocusGuard::new(settings.focus.as_ref(), !settings.no_interaction);
+    let no_interaction = settings.no_interaction;
+    if !confirm && no_interaction && focus.is_none() {
+        return Err(
+            "Watching without interaction requires focus.check, nobody could confirm redemptions",
+        );
+    }
+
+    let notifier = notify::from_config(settings.notifications.as_ref());
+    let mut interactor = interactor(settings)?;
+    // Codes that failed are not retried until the next start, a dead code would otherwise be tried every poll
+    let mut attempted: HashSet<Code> = HashSet::new();
+    // Codes the user was told about, waiting for the game does not repeat the notification
+    let mut notified: HashSet<Code> = HashSet::new();
+    let mut failing = false;
+
+    log(format!(
+        "Watching {} source(s) for new codes every {}, press Ctrl-C to stop",
+        sources.len(),
+        humanize(interval)
+    ));
+
+    loop {
+        let collected = match collect(&sources) {
+            Ok(collected) => {
+                failing = false;
+                collected
+            }
+            Err(e) => {
+                err!("Failed to retrieve codes: {}", e);
+                // Only the first failure in a row, the sources may be down for a while
+                if !failing {
+                    notify::send(notifier.as_deref(), &Event::Failed(e));
+                }
+                failing = true;
+                std::thread::sleep(interval);
+                continue;
+            }
+        };
+
+        let cache = Cache::from_file(&cache::path()).unwrap_or_else(|e| {
+            err!("Failed to read cache from file: {}", e);
+            Cache::new()
+        });
+
+        let now = Utc::now();
+        let mut codes: Vec<_> = collected
+            .codes
+            .into_iter()
+            .filter(|code| {
+                !code.is_expired(now)
+                    && !cache.contains(&code.code)
+                    && !attempted.contains(&code.code)
+            })
+            .collect();
+        codes.sort_by_key(|code| (code.expires_at.is_none(), code.expires_at));
+
+        if codes.is_empty() {
+            log("No new codes");
+        } else {
+            log(format!("{} new code(s):", codes.len()));
+            for code in &codes {
+                println!("  {}", code);
+            }
+
+            let unannounced: Vec<Code> = codes
+                .iter()
+                .map(|code| code.code.clone())
+                .filter(|code| notified.insert(code.clone()))
+                .collect();
+            if !unannounced.is_empty() {
+                notify::send(notifier.as_deref(), &Event::NewCodes(&unannounced));
+            }
+
+            if confirm {
+                println!("Ensure you are on the Chest menu (default hotkey 'o'), and press ENTER to start redemption.");
+                await_enter();
+            } else {
+                match focus.as_ref().and_then(FocusGuard::state) {
+                    Some(true) => {}
+                    Some(false) => {
+                        log("The game is not focused, trying again at the next poll");
+                        std::thread::sleep(interval);
+                        continue;
+                    }
+                    None if no_interaction => {
+                        log("Cannot tell whether the game is focused, trying again at the next poll");
+                        std::thread::sleep(interval);
+                        continue;
+                    }
+                    None => {
+                        println!("Cannot tell whether the game is focused. Ensure it is on the Chest menu (default hotkey 'o'), and press ENTER to start redemption.");
+                        await_enter();
+                    }
+                }
+            }
+
+            let expiry: HashMap<Code, DateTime<Utc>> = codes
+                .iter()
+                .filter_map(|code| code.expires_at.map(|at| (code.code.clone(), at)))
+                .collect();
+            let codes: Vec<Code> = codes.into_iter().map(|code| code.code).collect();
+            attempted.extend(codes.iter().cloned());
+
+            let result = interactor.redeem_many(codes);
+
+            let summary = interactor.take_summary().with_expiry(expiry);
+            if !summary.attempts.is_empty() {
+                println!("{}", summary);
+            }
+
+            if let Some(webhooks) = webhooks.as_ref().filter(|_| summary.has_codes()) {
+                webhooks.send(&summary);
+            }
+
+            if let Err(failed_codes) = result {
+                err!("Failed to redeem codes: {}", join(&failed_codes));
+            }
+
+            // The user took over, or asked us to stop
+            if abort::is_tripped() {
+                log(format!("Stopped watching: {}", abort::reason()));
+                notify::send(notifier.as_deref(), &Event::Failed(abort::reason()));
+                return Err(abort::reason());
+            }
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+/// Parse an interval such as "30m", "1h30m", "45s" or "2h".
+pub fn parse_interval(input: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid interval '{}', use e.g. 30m, 1h or 1h30m", input);
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("Interval '{}' is too long", input))?;
+        number.clear();
+    }
+
+    if !number.is_empty() || total == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+fn humanize(interval: Duration) -> String {
+    let secs = interval.as_secs();
+
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (h, 0, 0) if h > 0 => format!("{}h", h),
+        (h, m, 0) if h > 0 => format!("{}h{}m", h, m),
+        (0, m, 0) => format!("{}m", m),
+        (0, 0, s) => format!("{}s", s),
+        _ => format!("{}s", secs),
+    }
+}
+
+fn log(message: impl std::fmt::Display) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_intervals() {
+        let secs = |input| parse_interval(input).map(|interval| interval.as_secs());
+
+        assert_eq!(secs("45s"), Ok(45));
+        assert_eq!(secs("30m"), Ok(30 * 60));
+        assert_eq!(secs("2h"), Ok(2 * 60 * 60));
+        assert_eq!(secs("1h30m"), Ok(90 * 60));
+        assert_eq!(secs("1d"), Ok(24 * 60 * 60));
+        assert_eq!(secs(" 10m "), Ok(10 * 60));
+    }
+
+    #[test]
+    fn rejects_invalid_intervals() {
+        for input in ["", "30", "m", "0m", "1x", "1h30", "-5m", "1.5h"] {
+            assert!(parse_interval(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_intervals_that_overflow() {
+        assert!(parse_interval("18446744073709551615d").is_err());
+        assert!(parse_interval("18446744073709551615s1s").is_err());
+        assert!(parse_interval("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn humanizes_intervals() {
+        let humanize = |secs| humanize(Duration::from_secs(secs));
+
+        assert_eq!(humanize(45), "45s");
+        assert_eq!(humanize(30 * 60), "30m");
+        assert_eq!(humanize(2 * 60 * 60), "2h");
+        assert_eq!(humanize(90 * 60), "1h30m");
+        assert_eq!(humanize(25 * 60 * 60), "25h");
+        assert_eq!(humanize(61), "61s");
+    }
+}